@@ -0,0 +1,182 @@
+use crate::{
+	client::{Document, Error, Folder},
+	idb,
+};
+
+/// How much a document served from the cache can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Freshness {
+	/// Just downloaded from the server.
+	Fresh,
+	/// The server answered `304 Not Modified` to its `ETag`.
+	Revalidated,
+	/// The server could not be reached : the document may be outdated.
+	#[default]
+	Stale,
+	/// Written locally, the write is waiting in the outbox.
+	Pending,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CachedDocument {
+	document: Document,
+	/// `Date.now()` when the document was downloaded
+	fetched_at: f64,
+	/// `Date.now()` when the server last confirmed the document
+	validated_at: f64,
+	/// not sent to the server yet
+	#[serde(default)]
+	pending: bool,
+	// only meaningful for the read which returned this document
+	#[serde(skip_deserializing)]
+	freshness: Freshness,
+}
+impl CachedDocument {
+	pub(crate) fn fetched(document: Document) -> Self {
+		let now = js_sys::Date::now();
+
+		Self {
+			document,
+			fetched_at: now,
+			validated_at: now,
+			pending: false,
+			freshness: Freshness::Fresh,
+		}
+	}
+	pub(crate) fn pending(document: Document) -> Self {
+		let now = js_sys::Date::now();
+
+		Self {
+			document,
+			fetched_at: now,
+			validated_at: now,
+			pending: true,
+			freshness: Freshness::Pending,
+		}
+	}
+	pub(crate) fn revalidated(self) -> Self {
+		Self {
+			validated_at: js_sys::Date::now(),
+			freshness: Freshness::Revalidated,
+			..self
+		}
+	}
+	pub fn get_document(&self) -> &Document {
+		&self.document
+	}
+	pub fn into_document(self) -> Document {
+		self.document
+	}
+	pub fn get_fetched_at(&self) -> f64 {
+		self.fetched_at
+	}
+	pub fn get_validated_at(&self) -> f64 {
+		self.validated_at
+	}
+	pub fn get_freshness(&self) -> Freshness {
+		self.freshness
+	}
+	/// Time since the server last confirmed the document.
+	pub fn age(&self) -> std::time::Duration {
+		std::time::Duration::from_millis((js_sys::Date::now() - self.validated_at).max(0.0) as u64)
+	}
+}
+
+/// Keeps the last known version of documents in IndexedDB, in order to read
+/// them without network.
+#[derive(Debug)]
+pub struct DocumentCache {
+	database: String,
+}
+impl DocumentCache {
+	const VERSION: u32 = 2;
+	const STORE: &'static str = "documents";
+	const FOLDERS: &'static str = "folders";
+
+	pub fn new(database: impl Into<String>) -> Self {
+		Self {
+			database: database.into(),
+		}
+	}
+	async fn open(&self) -> Result<web_sys::IdbDatabase, Error> {
+		idb::open(&self.database, Self::VERSION, &[Self::STORE, Self::FOLDERS]).await
+	}
+	pub async fn get(&self, key: &str) -> Result<Option<CachedDocument>, Error> {
+		let db = self.open().await?;
+		let value = idb::get(&db, Self::STORE, key).await;
+		db.close();
+
+		match value? {
+			Some(value) => {
+				let mut cached: CachedDocument = serde_wasm_bindgen::from_value(value)?;
+				if cached.pending {
+					cached.freshness = Freshness::Pending;
+				}
+
+				Ok(Some(cached))
+			}
+			None => Ok(None),
+		}
+	}
+	pub async fn put(&self, key: &str, cached: &CachedDocument) -> Result<(), Error> {
+		let value = serde_wasm_bindgen::to_value(cached)?;
+
+		let db = self.open().await?;
+		let result = idb::put(&db, Self::STORE, key, &value).await;
+		db.close();
+
+		result
+	}
+	pub async fn remove(&self, key: &str) -> Result<(), Error> {
+		let db = self.open().await?;
+		let result = idb::delete(&db, Self::STORE, key).await;
+		db.close();
+
+		result
+	}
+	/// The listing of a folder at its last synchronization.
+	pub async fn get_folder(&self, key: &str) -> Result<Option<Folder>, Error> {
+		let db = self.open().await?;
+		let value = idb::get(&db, Self::FOLDERS, key).await;
+		db.close();
+
+		match value? {
+			Some(value) => Ok(Some(serde_wasm_bindgen::from_value(value)?)),
+			None => Ok(None),
+		}
+	}
+	pub async fn put_folder(&self, key: &str, folder: &Folder) -> Result<(), Error> {
+		let value = serde_wasm_bindgen::to_value(folder)?;
+
+		let db = self.open().await?;
+		let result = idb::put(&db, Self::FOLDERS, key, &value).await;
+		db.close();
+
+		result
+	}
+	pub async fn remove_folder(&self, key: &str) -> Result<(), Error> {
+		let db = self.open().await?;
+		let result = idb::delete(&db, Self::FOLDERS, key).await;
+		db.close();
+
+		result
+	}
+	/// Removes the documents and the folder listings whose key starts with
+	/// `prefix`.
+	pub async fn remove_all(&self, prefix: &str) -> Result<(), Error> {
+		let db = self.open().await?;
+		let mut result = idb::delete_all(&db, Self::STORE, prefix).await;
+		if result.is_ok() {
+			result = idb::delete_all(&db, Self::FOLDERS, prefix).await;
+		}
+		db.close();
+
+		result
+	}
+}
+impl Default for DocumentCache {
+	fn default() -> Self {
+		Self::new("remotestorage_cache")
+	}
+}
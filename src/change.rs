@@ -0,0 +1,83 @@
+use crate::client::Document;
+
+/// Where a change comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOrigin {
+	/// A write of this page, sent or queued in the outbox.
+	Local,
+	/// A new version found on the server, by a read or a synchronization.
+	Remote,
+	/// A change made by an other tab (or window) of the same origin.
+	Window,
+	/// A local write replaced by the resolution of a conflict.
+	Conflict,
+}
+impl ChangeOrigin {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Local => "local",
+			Self::Remote => "remote",
+			Self::Window => "window",
+			Self::Conflict => "conflict",
+		}
+	}
+}
+impl std::fmt::Display for ChangeOrigin {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// A document created, updated or removed ; `None` stands for a missing
+/// document.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Change {
+	path: String,
+	old_value: Option<Document>,
+	new_value: Option<Document>,
+	origin: ChangeOrigin,
+}
+impl Change {
+	pub fn new(
+		path: impl Into<String>,
+		old_value: Option<Document>,
+		new_value: Option<Document>,
+		origin: ChangeOrigin,
+	) -> Self {
+		Self {
+			path: path.into(),
+			old_value,
+			new_value,
+			origin,
+		}
+	}
+	pub(crate) fn with_origin(self, origin: ChangeOrigin) -> Self {
+		Self { origin, ..self }
+	}
+	pub fn get_path(&self) -> &str {
+		&self.path
+	}
+	pub fn get_old_value(&self) -> Option<&Document> {
+		self.old_value.as_ref()
+	}
+	pub fn get_new_value(&self) -> Option<&Document> {
+		self.new_value.as_ref()
+	}
+	pub fn get_origin(&self) -> ChangeOrigin {
+		self.origin
+	}
+	/// The document has been written again with the same version.
+	pub fn is_noop(&self) -> bool {
+		self.old_value == self.new_value
+	}
+	/// If the listeners of `path_prefix` are notified of this change.
+	pub fn concerns(&self, path_prefix: &str) -> bool {
+		self.path.starts_with(path_prefix)
+	}
+	/// If this change is shared with the other tabs, which is not the case of
+	/// the changes coming from them.
+	pub fn is_broadcast(&self) -> bool {
+		self.origin != ChangeOrigin::Window
+	}
+}
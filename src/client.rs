@@ -1,586 +1,2482 @@
 use js_sys::Promise;
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsCast, JsValue};
+
+use crate::{
+	cache, change, conflict, i18n, oauth, outbox, scope, token_store, utils, webfinger, widget,
+};
+
+const WEB_AUTHORING_FOLDER: &str = "/public/www/";
+// see `www/oauth-callback.html`
+const POPUP_CALLBACK_PATH: &str = "/oauth-callback.html";
+const POPUP_MESSAGE_TYPE: &str = "remotestorage-oauth";
+const POPUP_WINDOW_NAME: &str = "remotestorage_oauth";
+// the document may be changed again while a conflict is resolved
+const MAX_CONFLICT_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	NotConnected,
+	Unauthorized,
+	Forbidden,
+	NotFound {
+		path: String,
+	},
+	PreconditionFailed {
+		path: String,
+	},
+	InvalidPath {
+		path: String,
+		reason: &'static str,
+	},
+	InvalidScope {
+		scope: String,
+		reason: &'static str,
+	},
+	InvalidUserAddress {
+		address: String,
+		reason: &'static str,
+	},
+	InvalidClientId {
+		client_id: String,
+		reason: &'static str,
+	},
+	OutOfScope {
+		path: String,
+	},
+	OAuthStateMismatch,
+	OAuth(oauth::AuthError),
+	MissingConfiguration(&'static str),
+	Http {
+		status: u16,
+	},
+	Network(String),
+	Timeout(std::time::Duration),
+	MalformedResponse(String),
+	Webfinger(String),
+	Unsupported(&'static str),
+	Js(String),
+}
+impl Error {
+	fn from_status(status: u16, path: &str) -> Self {
+		match status {
+			401 => Self::Unauthorized,
+			403 => Self::Forbidden,
+			404 => Self::NotFound {
+				path: String::from(path),
+			},
+			412 => Self::PreconditionFailed {
+				path: String::from(path),
+			},
+			status => Self::Http { status },
+		}
+	}
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Self::NotConnected => "NotConnected",
+			Self::Unauthorized => "Unauthorized",
+			Self::Forbidden => "Forbidden",
+			Self::NotFound { .. } => "NotFound",
+			Self::PreconditionFailed { .. } => "PreconditionFailed",
+			Self::InvalidPath { .. } => "InvalidPath",
+			Self::InvalidScope { .. } => "InvalidScope",
+			Self::InvalidUserAddress { .. } => "InvalidUserAddress",
+			Self::InvalidClientId { .. } => "InvalidClientId",
+			Self::OutOfScope { .. } => "OutOfScope",
+			Self::OAuthStateMismatch => "OAuthStateMismatch",
+			Self::OAuth(_) => "OAuth",
+			Self::MissingConfiguration(_) => "MissingConfiguration",
+			Self::Http { .. } => "Http",
+			Self::Network(_) => "Network",
+			Self::Timeout(_) => "Timeout",
+			Self::MalformedResponse(_) => "MalformedResponse",
+			Self::Webfinger(_) => "Webfinger",
+			Self::Unsupported(_) => "Unsupported",
+			Self::Js(_) => "Js",
+		}
+	}
+}
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NotConnected => f.write_str("client is not connected"),
+			Self::Unauthorized => f.write_str("access token is missing, invalid or expired"),
+			Self::Forbidden => f.write_str("access token does not grant access to this path"),
+			Self::NotFound { path } => {
+				write!(f, "document `{}` does not exists yet in database", path)
+			}
+			Self::PreconditionFailed { path } => write!(
+				f,
+				"document `{}` has been changed on server since the given `ETag`",
+				path
+			),
+			Self::InvalidPath { path, reason } => write!(f, "invalid path `{}` : {}", path, reason),
+			Self::InvalidScope { scope, reason } => {
+				write!(f, "invalid scope `{}` : {}", scope, reason)
+			}
+			Self::MissingConfiguration(setting) => {
+				write!(f, "`{}` must be set before building the client", setting)
+			}
+			Self::InvalidUserAddress { address, reason } => {
+				write!(f, "invalid user address `{}` : {}", address, reason)
+			}
+			Self::InvalidClientId { client_id, reason } => {
+				write!(f, "invalid client id `{}` : {}", client_id, reason)
+			}
+			Self::OutOfScope { path } => write!(
+				f,
+				"no `rw` scope has been requested for writing in `{}`",
+				path
+			),
+			Self::OAuthStateMismatch => f.write_str(
+				"the `state` of the OAuth response does not match the one of this client's request",
+			),
+			Self::OAuth(err) => write!(f, "authorization has been refused : {}", err),
+			Self::Http { status } => write!(f, "error {} when access to database", status),
+			Self::Network(err) => write!(f, "can not reach the server : {}", err),
+			Self::Timeout(timeout) => {
+				write!(f, "server did not answer within {} ms", timeout.as_millis())
+			}
+			Self::MalformedResponse(err) => write!(f, "unexpected server response : {}", err),
+			Self::Webfinger(err) => write!(f, "webfinger discovery failed : {}", err),
+			Self::Unsupported(capability) => {
+				write!(
+					f,
+					"server does not advertise the `{}` capability",
+					capability
+				)
+			}
+			Self::Js(err) => f.write_str(err),
+		}
+	}
+}
+impl std::error::Error for Error {}
+impl From<JsValue> for Error {
+	fn from(err: JsValue) -> Self {
+		Self::Js(js_error_message(&err))
+	}
+}
+impl From<serde_wasm_bindgen::Error> for Error {
+	fn from(err: serde_wasm_bindgen::Error) -> Self {
+		Self::Js(err.to_string())
+	}
+}
+impl From<Error> for JsValue {
+	fn from(err: Error) -> Self {
+		let result = js_sys::Error::new(&err.to_string());
+
+		js_sys::Reflect::set(&result, &"kind".into(), &err.kind().into()).ok();
+		if let Error::Http { status } = err {
+			js_sys::Reflect::set(&result, &"status".into(), &status.into()).ok();
+		}
+
+		result.into()
+	}
+}
+
+fn js_error_message(err: &JsValue) -> String {
+	match err.dyn_ref::<js_sys::Error>() {
+		Some(err) => String::from(err.message()),
+		None => err.as_string().unwrap_or_else(|| format!("{:?}", err)),
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+struct RequestSettings {
+	timeout: Option<std::time::Duration>,
+	logging: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEvent {
+	Connected,
+	Disconnected,
+	/// The token has expired or has been rejected by the server : the user
+	/// has to connect again, with `show_connect_overlay` for example.
+	ReauthRequired,
+}
+impl ConnectionEvent {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Connected => "connected",
+			Self::Disconnected => "disconnected",
+			Self::ReauthRequired => "reauth_required",
+		}
+	}
+}
+impl std::fmt::Display for ConnectionEvent {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+type ChannelListener = Closure<dyn FnMut(web_sys::MessageEvent)>;
+
+// Connection state, shared with the futures of the JS bindings which can not
+// borrow the `ClientRemote`.
+struct Session {
+	client: std::cell::RefCell<Option<Client>>,
+	listeners: utils::Listeners<dyn Fn(&ConnectionEvent)>,
+	change_listeners: utils::Listeners<dyn Fn(&change::Change)>,
+	// shares the changes with the other tabs
+	channel: Option<web_sys::BroadcastChannel>,
+	token_store: std::rc::Rc<dyn token_store::TokenStore>,
+	token_key: String,
+	client_id: String,
+	cache: Option<cache::DocumentCache>,
+	outbox: Option<outbox::Outbox>,
+	resolver: Option<Box<dyn conflict::ConflictResolver>>,
+	// documents of different accounts must not be mixed in the cache and the
+	// outbox
+	namespace: String,
+	replaying: std::cell::Cell<bool>,
+	settings: RequestSettings,
+}
+impl Session {
+	fn notify(&self, event: ConnectionEvent) {
+		self.listeners.for_each(|listener| listener(&event));
+	}
+	fn mount(&self, client: Client) {
+		self.client.replace(Some(client));
+		self.notify(ConnectionEvent::Connected);
+	}
+	fn unmount(&self) -> Option<Client> {
+		let client = self.client.take();
+		if client.is_some() {
+			self.notify(ConnectionEvent::Disconnected);
+		}
+
+		client
+	}
+	/// Mounts the saved token, if saved with its storage link, without
+	/// asking the server.
+	async fn mount_saved(&self) -> Result<bool, Error> {
+		let token = match self.token_store.load(&self.token_key).await? {
+			Some(token) => token_store::StoredToken::parse(&token),
+			None => return Ok(false),
+		};
+
+		match Client::from_token(token, &self.settings) {
+			Some(client) => {
+				self.mount(client);
+
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+	fn mounted_client(&self) -> Result<Client, Error> {
+		self.client.borrow().clone().ok_or(Error::NotConnected)
+	}
+	async fn client(&self) -> Result<Client, Error> {
+		let client = self.mounted_client()?;
+
+		if client.token.is_expired() {
+			return match client.refresh(&self.client_id).await {
+				Ok(client) => {
+					self.token_store
+						.save(&self.token_key, &client.token.serialize())
+						.await?;
+					self.client.replace(Some(client.clone()));
+
+					Ok(client)
+				}
+				// the token may still be refreshed once back online
+				Err(err @ (Error::Network(_) | Error::Timeout(_))) => Err(err),
+				Err(_) => {
+					self.expire().await;
+
+					Err(Error::Unauthorized)
+				}
+			};
+		}
+
+		Ok(client)
+	}
+	async fn checked<T>(
+		&self,
+		request: impl std::future::Future<Output = Result<T, Error>>,
+	) -> Result<T, Error> {
+		let result = request.await;
+
+		if let Err(Error::Unauthorized | Error::Forbidden) = result {
+			self.expire().await;
+		}
+
+		result
+	}
+	async fn expire(&self) {
+		if self.client.take().is_some() {
+			if let Err(err) = self.token_store.remove(&self.token_key).await {
+				web_sys::console::error_1(&err.into());
+			}
+
+			self.notify(ConnectionEvent::ReauthRequired);
+		}
+	}
+	/// Forgets the saved token and the documents of the account, then asks
+	/// the server to revoke the token of `client` if it advertises a
+	/// revocation endpoint.
+	async fn forget(&self, client: Option<Client>, client_id: &str) -> Result<(), Error> {
+		self.token_store.remove(&self.token_key).await?;
+
+		// the next user of the browser must not see them
+		if let Some(cache) = &self.cache {
+			self.log_cache_error(cache.remove_all(&self.cache_key("/")).await);
+		}
+		if let Some(outbox) = &self.outbox {
+			self.log_cache_error(outbox.clear(&self.namespace).await);
+		}
+
+		if let Some(client) = client {
+			client.revoke_token(client_id).await?;
+		}
+
+		Ok(())
+	}
+	/// Reads `path` from the server, then falls back to the cache when the
+	/// server can not be reached.
+	async fn read_document(&self, path: String) -> Result<cache::CachedDocument, Error> {
+		// the cache belongs to the connected account only
+		self.mounted_client()?;
+
+		let cache = match &self.cache {
+			Some(cache) => cache,
+			None => {
+				let client = self.client().await?;
+
+				return match self.checked(client.get_document(path, None)).await? {
+					DocumentResponse::Content(document) => {
+						Ok(cache::CachedDocument::fetched(document))
+					}
+					DocumentResponse::NotModified => Err(Error::MalformedResponse(String::from(
+						"304 Not Modified without If-None-Match",
+					))),
+				};
+			}
+		};
+
+		let key = self.cache_key(&path);
+		let cached = match cache.get(&key).await {
+			Ok(cached) => cached,
+			// the server may still answer
+			Err(err) => {
+				web_sys::console::error_1(&err.into());
+				None
+			}
+		};
+
+		// the server does not know the queued writes yet
+		match cached {
+			Some(cached) if cached.get_freshness() == cache::Freshness::Pending => {
+				return Ok(cached)
+			}
+			_ => {}
+		}
+
+		let response = if is_online() {
+			let etag = cached
+				.as_ref()
+				.and_then(|cached| cached.get_document().get_etag())
+				.map(String::from);
+
+			match self.client().await {
+				Ok(client) => self.checked(client.get_document(path.as_str(), etag)).await,
+				Err(err) => Err(err),
+			}
+		} else {
+			Err(Error::Network(String::from("the browser is offline")))
+		};
+
+		match response {
+			Ok(DocumentResponse::Content(document)) => {
+				let fetched = cache::CachedDocument::fetched(document);
+				self.store(&path, Some(fetched.clone()), change::ChangeOrigin::Remote)
+					.await;
+
+				Ok(fetched)
+			}
+			Ok(DocumentResponse::NotModified) => match cached {
+				Some(cached) => {
+					let revalidated = cached.revalidated();
+					self.log_cache_error(cache.put(&key, &revalidated).await);
+
+					Ok(revalidated)
+				}
+				None => Err(Error::MalformedResponse(String::from(
+					"304 Not Modified without If-None-Match",
+				))),
+			},
+			Err(err @ Error::NotFound { .. }) => {
+				self.store(&path, None, change::ChangeOrigin::Remote).await;
+
+				Err(err)
+			}
+			Err(err @ (Error::Network(_) | Error::Timeout(_))) => cached.ok_or(err),
+			Err(err) => Err(err),
+		}
+	}
+	/// Returns the new `ETag` of the document, which is unknown when the
+	/// write has been queued in the outbox.
+	async fn put_document(
+		&self,
+		path: String,
+		document: &Document,
+		precondition: Precondition,
+	) -> Result<Option<String>, Error> {
+		if self.must_queue().await {
+			self.queue(path, outbox::Operation::Put(document.clone()), precondition)
+				.await?;
+			if is_online() {
+				// the older writes are still waiting for the server
+				if let Err(err) = self.replay_outbox().await {
+					web_sys::console::error_1(&err.into());
+				}
+			}
+
+			return Ok(None);
+		}
+
+		let client = self.client().await?;
+		// the cache may hold a newer version by the time the server answers
+		let base = self.conflict_base(&path, &precondition).await;
+		match self
+			.checked(client.put_document(path.as_str(), document, &precondition))
+			.await
+		{
+			Ok(etag) => {
+				self.cache_written(
+					&path,
+					Some(document.clone().with_etag(etag.clone())),
+					change::ChangeOrigin::Local,
+				)
+				.await;
+
+				Ok(etag)
+			}
+			Err(Error::PreconditionFailed { .. }) if self.resolver.is_some() => {
+				let written = self
+					.resolve_conflict(
+						&client,
+						&path,
+						base,
+						Some(document.clone()),
+						js_sys::Date::now(),
+					)
+					.await?;
+				let etag = written
+					.as_ref()
+					.and_then(|written| written.get_etag())
+					.map(String::from);
+				self.cache_written(&path, written, change::ChangeOrigin::Conflict)
+					.await;
+
+				Ok(etag)
+			}
+			Err(Error::Network(_) | Error::Timeout(_)) if self.outbox.is_some() => self
+				.queue(path, outbox::Operation::Put(document.clone()), precondition)
+				.await
+				.map(|_| None),
+			Err(err) => Err(err),
+		}
+	}
+	/// Returns if the document existed, which is assumed when the deletion
+	/// has been queued in the outbox.
+	async fn delete_document(&self, path: String, if_match: Option<String>) -> Result<bool, Error> {
+		if self.must_queue().await {
+			self.queue(path, outbox::Operation::Delete, if_match.into())
+				.await?;
+			if is_online() {
+				// the older writes are still waiting for the server
+				if let Err(err) = self.replay_outbox().await {
+					web_sys::console::error_1(&err.into());
+				}
+			}
+
+			return Ok(true);
+		}
+
+		let client = self.client().await?;
+		let base = self.conflict_base(&path, &if_match.clone().into()).await;
+		match self
+			.checked(client.delete_document(path.as_str(), if_match.clone()))
+			.await
+		{
+			Ok(existed) => {
+				self.cache_written(&path, None, change::ChangeOrigin::Local)
+					.await;
+
+				Ok(existed)
+			}
+			Err(Error::PreconditionFailed { .. }) if self.resolver.is_some() => {
+				let written = self
+					.resolve_conflict(&client, &path, base, None, js_sys::Date::now())
+					.await?;
+				self.cache_written(&path, written, change::ChangeOrigin::Conflict)
+					.await;
+
+				Ok(true)
+			}
+			Err(Error::Network(_) | Error::Timeout(_)) if self.outbox.is_some() => self
+				.queue(path, outbox::Operation::Delete, if_match.into())
+				.await
+				.map(|_| true),
+			Err(err) => Err(err),
+		}
+	}
+	// Writes go through the outbox while offline, and while older writes are
+	// waiting in it, in order to keep their order.
+	async fn must_queue(&self) -> bool {
+		let outbox = match &self.outbox {
+			Some(outbox) => outbox,
+			None => return false,
+		};
+		if !is_online() {
+			return true;
+		}
+
+		match outbox.pending(&self.namespace).await {
+			Ok(writes) => !writes.is_empty(),
+			// the write is then sent right away
+			Err(err) => {
+				web_sys::console::error_1(&err.into());
+				false
+			}
+		}
+	}
+	async fn queue(
+		&self,
+		path: String,
+		operation: outbox::Operation,
+		precondition: Precondition,
+	) -> Result<(), Error> {
+		let outbox = self.outbox.as_ref().ok_or(Error::Unsupported("outbox"))?;
+
+		// a write made on a queued version, which has no `ETag` yet, is sent
+		// on the version of the queued write
+		let following = match precondition {
+			Precondition::CreateOnly => outbox
+				.pending(&self.namespace)
+				.await?
+				.iter()
+				.rev()
+				.find(|write| write.get_path() == path)
+				.and_then(outbox::PendingWrite::following),
+			Precondition::Unconditional | Precondition::IfMatch(_) => None,
+		};
+		let (precondition, base) = match following {
+			Some(following) => following,
+			// before being replaced by the write below
+			None => {
+				let base = self.cached_base(&path, &precondition).await;
+
+				(precondition, base)
+			}
+		};
+
+		// the local reads see the write right away
+		let pending = match &operation {
+			outbox::Operation::Put(document) => {
+				Some(cache::CachedDocument::pending(document.clone()))
+			}
+			outbox::Operation::Delete => None,
+		};
+		self.store(&path, pending, change::ChangeOrigin::Local)
+			.await;
+
+		outbox
+			.push(&self.namespace, path, operation, precondition, base)
+			.await?;
+
+		Ok(())
+	}
+	/// Sends the queued writes to the server, in order, and stops at the first
+	/// one which can not reach it, or which the server can not handle for now
+	/// (`429` and `5xx` statuses).
+	///
+	/// Writes rejected because the document has been changed in the meantime
+	/// go through the conflict resolver. Without it, or when rejected for
+	/// other reasons (`4xx` statuses), they are dropped, and their document is
+	/// removed from the cache in order to be fetched again.
+	async fn replay_outbox(&self) -> Result<(), Error> {
+		let outbox = match &self.outbox {
+			Some(outbox) => outbox,
+			None => return Ok(()),
+		};
+		if self.replaying.replace(true) {
+			return Ok(());
+		}
+
+		let result = self.replay_writes(outbox).await;
+		self.replaying.set(false);
+
+		result
+	}
+	async fn replay_writes(&self, outbox: &outbox::Outbox) -> Result<(), Error> {
+		let mut chain = outbox::ReplayChain::default();
+
+		// writes may be queued while the previous ones are sent
+		loop {
+			let writes = outbox.pending(&self.namespace).await?;
+			if writes.is_empty() {
+				return Ok(());
+			}
+
+			self.replay_batch(outbox, &writes, &mut chain).await?;
+		}
+	}
+	async fn replay_batch(
+		&self,
+		outbox: &outbox::Outbox,
+		writes: &[outbox::PendingWrite],
+		chain: &mut outbox::ReplayChain,
+	) -> Result<(), Error> {
+		let client = self.client().await?;
+
+		for (index, write) in writes.iter().enumerate() {
+			let path = write.get_path();
+			let precondition = chain.precondition(write);
+
+			let local = match write.get_operation() {
+				outbox::Operation::Put(document) => Some(document.clone()),
+				outbox::Operation::Delete => None,
+			};
+			let result = match &local {
+				Some(document) => self
+					.checked(client.put_document(path, document, &precondition))
+					.await
+					.map(|etag| Some(document.clone().with_etag(etag))),
+				None => self
+					.checked(
+						client.delete_document(path, precondition.get_etag().map(String::from)),
+					)
+					.await
+					.map(|_| None),
+			};
+			let result = match result {
+				Err(Error::PreconditionFailed { .. }) if self.resolver.is_some() => {
+					chain.rejected(path);
+
+					self.resolve_conflict(
+						&client,
+						path,
+						write.get_base().clone(),
+						local,
+						write.get_queued_at(),
+					)
+					.await
+					.map(|written| (written, true))
+				}
+				result => result.map(|written| (written, false)),
+			};
+
+			match result {
+				Ok((written, resolved)) => {
+					outbox.remove(write).await?;
+
+					let etag = written
+						.as_ref()
+						.and_then(|written| written.get_etag())
+						.map(String::from);
+					let overwritten = writes[index + 1..]
+						.iter()
+						.any(|next| next.get_path() == path);
+					if !overwritten {
+						let origin = if resolved {
+							change::ChangeOrigin::Conflict
+						} else {
+							change::ChangeOrigin::Local
+						};
+						self.cache_written(path, written, origin).await;
+					}
+
+					if !resolved {
+						chain.accepted(write, etag);
+					}
+				}
+				// the server will never accept them
+				Err(
+					err @ (Error::NotFound { .. }
+					| Error::PreconditionFailed { .. }
+					| Error::Http {
+						status: 400..=428 | 430..=499,
+					}),
+				) => {
+					web_sys::console::error_1(&err.into());
+					outbox.remove(write).await?;
+
+					if let Some(cache) = &self.cache {
+						self.log_cache_error(cache.remove(&self.cache_key(path)).await);
+					}
+				}
+				// kept for the next replay
+				Err(err) => return Err(err),
+			}
+		}
 
-lazy_static::lazy_static! {
-	static ref ACCESS_TOKEN_REGEX: regex::Regex = regex::Regex::new("^#.*access_token=([^&]+).+$").unwrap();
+		Ok(())
+	}
+	/// Asks the conflict resolver what to write instead of `local`, then
+	/// writes it. Returns the version left on the server, if any.
+	async fn resolve_conflict(
+		&self,
+		client: &Client,
+		path: &str,
+		base: conflict::Base,
+		local: Option<Document>,
+		local_modified_at: f64,
+	) -> Result<Option<Document>, Error> {
+		let resolver = self
+			.resolver
+			.as_ref()
+			.ok_or_else(|| Error::PreconditionFailed {
+				path: String::from(path),
+			})?;
+
+		for _ in 0..MAX_CONFLICT_ATTEMPTS {
+			let remote = match self.checked(client.get_document(path, None)).await {
+				Ok(DocumentResponse::Content(remote)) => Some(remote),
+				Ok(DocumentResponse::NotModified) => {
+					return Err(Error::MalformedResponse(String::from(
+						"304 Not Modified without If-None-Match",
+					)))
+				}
+				Err(Error::NotFound { .. }) => None,
+				Err(err) => return Err(err),
+			};
+			let remote_etag = remote
+				.as_ref()
+				.and_then(|remote| remote.get_etag())
+				.map(String::from);
+
+			let conflict = conflict::Conflict::new(
+				path,
+				base.clone(),
+				local.clone(),
+				remote,
+				local_modified_at,
+			);
+			let resolved = match resolver.resolve(&conflict) {
+				conflict::Resolution::Remote => return Ok(conflict.into_remote()),
+				conflict::Resolution::Local => local.clone(),
+				// not written again
+				conflict::Resolution::Merged(merged)
+					if merged.as_ref() == conflict.get_remote() =>
+				{
+					return Ok(conflict.into_remote())
+				}
+				conflict::Resolution::Merged(merged) => merged,
+			};
+
+			let result = match (resolved, remote_etag) {
+				(Some(document), remote_etag) => {
+					let precondition = Precondition::from_etag(remote_etag.as_deref());
+					self.checked(client.put_document(path, &document, &precondition))
+						.await
+						.map(|etag| Some(document.with_etag(etag)))
+				}
+				(None, Some(remote_etag)) => self
+					.checked(client.delete_document(path, Some(remote_etag)))
+					.await
+					.map(|_| None),
+				// already removed
+				(None, None) => Ok(None),
+			};
+
+			match result {
+				Err(Error::PreconditionFailed { .. }) => continue,
+				result => return result,
+			}
+		}
+
+		Err(Error::PreconditionFailed {
+			path: String::from(path),
+		})
+	}
+	/// The version a write with `precondition` is made on, if there is a
+	/// conflict resolver to give it to.
+	async fn conflict_base(&self, path: &str, precondition: &Precondition) -> conflict::Base {
+		match self.resolver {
+			Some(_) => self.cached_base(path, precondition).await,
+			None => conflict::Base::Unknown,
+		}
+	}
+	/// The version a write with `precondition` is made on, if still cached.
+	async fn cached_base(&self, path: &str, precondition: &Precondition) -> conflict::Base {
+		let etag = match precondition {
+			Precondition::IfMatch(etag) => etag,
+			Precondition::CreateOnly => return conflict::Base::Missing,
+			Precondition::Unconditional => return conflict::Base::Unknown,
+		};
+		let cached = match &self.cache {
+			Some(cache) => cache.get(&self.cache_key(path)).await.ok().flatten(),
+			None => None,
+		};
+
+		match cached {
+			Some(cached) if cached.get_document().get_etag() == Some(etag) => {
+				conflict::Base::Known(cached.into_document())
+			}
+			_ => conflict::Base::Unknown,
+		}
+	}
+	/// Replaces the cached version of `path` by the one now on the server.
+	async fn cache_written(
+		&self,
+		path: &str,
+		written: Option<Document>,
+		origin: change::ChangeOrigin,
+	) {
+		self.store(path, written.map(cache::CachedDocument::fetched), origin)
+			.await;
+	}
+	/// Replaces the cached version of `path`, then notifies the change.
+	async fn store(
+		&self,
+		path: &str,
+		cached: Option<cache::CachedDocument>,
+		origin: change::ChangeOrigin,
+	) {
+		let mut old_value = None;
+
+		if let Some(cache) = &self.cache {
+			let key = self.cache_key(path);
+			old_value = match cache.get(&key).await {
+				Ok(old) => old.map(cache::CachedDocument::into_document),
+				Err(err) => {
+					web_sys::console::error_1(&err.into());
+					None
+				}
+			};
+			self.log_cache_error(match &cached {
+				Some(cached) => cache.put(&key, cached).await,
+				None => cache.remove(&key).await,
+			});
+		}
+
+		self.notify_change(change::Change::new(
+			path,
+			old_value,
+			cached.map(cache::CachedDocument::into_document),
+			origin,
+		));
+	}
+	fn notify_change(&self, change: change::Change) {
+		if change.is_noop() {
+			return;
+		}
+
+		self.change_listeners.for_each(|listener| listener(&change));
+
+		if let (Some(channel), true) = (&self.channel, change.is_broadcast()) {
+			let message = serde_wasm_bindgen::to_value(&change)
+				.map_err(Error::from)
+				.and_then(|message| Ok(channel.post_message(&message)?));
+			if let Err(err) = message {
+				web_sys::console::error_1(&err.into());
+			}
+		}
+	}
+	fn cache_key(&self, path: &str) -> String {
+		format!("{}{}", self.namespace, path)
+	}
+	// the server answered, so a failing cache should not fail the request
+	fn log_cache_error(&self, result: Result<(), Error>) {
+		if let Err(err) = result {
+			web_sys::console::error_1(&err.into());
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct ClientRemoteBuilder {
+	webfinger_root_uri: Option<String>,
+	user_address: Option<webfinger::UserAddress>,
+	scopes: Vec<scope::Scope>,
+	client_id: Option<String>,
+	redirect_uri: oauth::RedirectUri,
+	oauth_flow: oauth::OAuthFlow,
+	connect_mode: oauth::ConnectMode,
+	catalog: Option<i18n::Catalog>,
+	token_store: Option<std::rc::Rc<dyn token_store::TokenStore>>,
+	// the token is then kept in the same cookie as the previous versions
+	cookie_token_store: bool,
+	cache: Option<cache::DocumentCache>,
+	outbox: Option<outbox::Outbox>,
+	resolver: Option<Box<dyn conflict::ConflictResolver>>,
+	timeout: Option<std::time::Duration>,
+	logging: bool,
 }
+impl ClientRemoteBuilder {
+	pub fn user_address(mut self, user_address: webfinger::UserAddress) -> Self {
+		self.user_address = Some(user_address);
+		self
+	}
+	pub fn webfinger_root(mut self, webfinger_root_uri: impl Into<String>) -> Self {
+		self.webfinger_root_uri = Some(webfinger_root_uri.into());
+		self
+	}
+	pub fn scope(mut self, scope: scope::Scope) -> Self {
+		self.scopes.push(scope);
+		self
+	}
+	pub fn scopes(mut self, scopes: impl IntoIterator<Item = scope::Scope>) -> Self {
+		self.scopes.extend(scopes);
+		self
+	}
+	pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+		self.client_id = Some(client_id.into());
+		self
+	}
+	pub fn redirect_uri(mut self, redirect_uri: impl Into<String>) -> Self {
+		self.redirect_uri = oauth::RedirectUri::Explicit(redirect_uri.into());
+		self
+	}
+	pub fn redirect_uri_policy(mut self, redirect_uri: oauth::RedirectUri) -> Self {
+		self.redirect_uri = redirect_uri;
+		self
+	}
+	pub fn oauth_flow(mut self, oauth_flow: oauth::OAuthFlow) -> Self {
+		self.oauth_flow = oauth_flow;
+		self
+	}
+	pub fn connect_mode(mut self, connect_mode: oauth::ConnectMode) -> Self {
+		self.connect_mode = connect_mode;
+		self
+	}
+	/// Texts of the connect overlay, which follow `navigator.language` by
+	/// default.
+	pub fn catalog(mut self, catalog: i18n::Catalog) -> Self {
+		self.catalog = Some(catalog);
+		self
+	}
+	pub fn token_store(mut self, token_store: impl token_store::TokenStore + 'static) -> Self {
+		self.cookie_token_store =
+			(&token_store as &dyn std::any::Any).is::<token_store::CookieTokenStore>();
+		self.token_store = Some(std::rc::Rc::new(token_store));
+		self
+	}
+	/// Keeps the read and written documents, in order to read them while
+	/// offline. See `ClientRemote::read_document`.
+	pub fn cache(mut self, cache: cache::DocumentCache) -> Self {
+		self.cache = Some(cache);
+		self
+	}
+	/// Queues the writes made while the server can not be reached, and
+	/// replays them once the browser is back online.
+	pub fn outbox(mut self, outbox: outbox::Outbox) -> Self {
+		self.outbox = Some(outbox);
+		self
+	}
+	/// Decides what to write when a write is rejected because the document
+	/// has been changed since it was read. Without it, the write fails with
+	/// `Error::PreconditionFailed`, or is dropped from the outbox.
+	pub fn conflict_resolver(
+		mut self,
+		resolver: impl conflict::ConflictResolver + 'static,
+	) -> Self {
+		self.resolver = Some(Box::new(resolver));
+		self
+	}
+	pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+	pub fn logging(mut self, logging: bool) -> Self {
+		self.logging = logging;
+		self
+	}
+	pub fn build(self) -> Result<ClientRemote, Error> {
+		let user_address = self
+			.user_address
+			.ok_or(Error::MissingConfiguration("user address"))?;
+
+		if self.scopes.is_empty() {
+			return Err(Error::MissingConfiguration("scope"));
+		}
+
+		let client_id = match self.client_id {
+			Some(client_id) => client_id,
+			None => utils::window()?.location().origin()?,
+		};
+
+		let cookie_name_header =
+			generate_cookie_name_header(&client_id, &user_address, &self.scopes)?;
+		let token_key = format!("{}access_token", cookie_name_header);
+		if !self.cookie_token_store {
+			// previous versions kept the token in a cookie, sent along with
+			// every request to the application
+			if let Err(err) = token_store::remove_legacy_cookie(&token_key) {
+				web_sys::console::error_1(&err.into());
+			}
+		}
+
+		let namespace = user_address.to_string();
+		let settings = RequestSettings {
+			timeout: self.timeout,
+			logging: self.logging,
+		};
+		let has_outbox = self.outbox.is_some();
+		// not available in every browser, the changes then stay in this tab
+		let channel = web_sys::BroadcastChannel::new(&format!("remotestorage:{}", namespace)).ok();
+
+		let session = std::rc::Rc::new(Session {
+			client: std::cell::RefCell::new(None),
+			listeners: utils::Listeners::default(),
+			change_listeners: utils::Listeners::default(),
+			channel,
+			token_store: self
+				.token_store
+				.unwrap_or_else(|| std::rc::Rc::new(token_store::LocalStorageTokenStore)),
+			token_key,
+			client_id: client_id.clone(),
+			cache: self.cache,
+			outbox: self.outbox,
+			resolver: self.resolver,
+			namespace,
+			replaying: std::cell::Cell::new(false),
+			settings: settings.clone(),
+		});
+
+		let online_listener = if has_outbox {
+			Some(listen_online(&session)?)
+		} else {
+			None
+		};
+		let channel_listener = listen_channel(&session);
+
+		Ok(ClientRemote {
+			user_address,
+			webfinger_root_uri: self.webfinger_root_uri,
+			scopes: self.scopes,
+			client_id,
+			cookie_name_header,
+			redirect_uri: self.redirect_uri,
+			oauth_flow: self.oauth_flow,
+			connect_mode: self.connect_mode,
+			catalog: self.catalog,
+			settings,
+			session,
+			online_listener,
+			channel_listener,
+		})
+	}
+}
+
+// The next address submitted through `widget`, `None` when the user aborts.
+async fn next_address(
+	widget: &widget::ConnectWidget,
+) -> Result<Option<webfinger::UserAddress>, Error> {
+	let mut resolve = None;
+	let submitted = Promise::new(&mut |resolve_fn, _| resolve = Some(resolve_fn));
+	let resolve = resolve.ok_or_else(|| Error::Js(String::from("can not create a Promise")))?;
+
+	let listener = widget.on_action(move |action| match action {
+		widget::WidgetAction::AddressSubmitted(address) => {
+			resolve
+				.call1(&JsValue::NULL, &JsValue::from_str(&address.to_string()))
+				.ok();
+		}
+		widget::WidgetAction::Abort => {
+			resolve.call1(&JsValue::NULL, &JsValue::NULL).ok();
+		}
+		_ => {}
+	});
+
+	let address = wasm_bindgen_futures::JsFuture::from(submitted).await;
+	widget.remove_action_listener(listener);
+
+	Ok(address?
+		.as_string()
+		.and_then(|address| address.parse().ok()))
+}
+
+// Notifies the changes made by the other tabs.
+fn listen_channel(session: &std::rc::Rc<Session>) -> Option<ChannelListener> {
+	let channel = session.channel.as_ref()?;
+
+	let weak_session = std::rc::Rc::downgrade(session);
+	let listener = Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+		if let Some(session) = weak_session.upgrade() {
+			match serde_wasm_bindgen::from_value::<change::Change>(event.data()) {
+				Ok(change) => {
+					session.notify_change(change.with_origin(change::ChangeOrigin::Window))
+				}
+				Err(err) => web_sys::console::error_1(&Error::from(err).into()),
+			}
+		}
+	}) as Box<dyn FnMut(web_sys::MessageEvent)>);
+	channel.set_onmessage(Some(listener.as_ref().unchecked_ref()));
+
+	Some(listener)
+}
+
+// Replays the outbox each time the browser goes back online, once mounted
+// again if the page has been opened offline.
+fn listen_online(session: &std::rc::Rc<Session>) -> Result<Closure<dyn FnMut()>, Error> {
+	let weak_session = std::rc::Rc::downgrade(session);
+	let listener = Closure::wrap(Box::new(move || {
+		if let Some(session) = weak_session.upgrade() {
+			wasm_bindgen_futures::spawn_local(async move {
+				if session.client.borrow().is_none() {
+					match session.mount_saved().await {
+						Ok(true) => {}
+						Ok(false) => return,
+						Err(err) => return web_sys::console::error_1(&err.into()),
+					}
+				}
+
+				if let Err(err) = session.replay_outbox().await {
+					web_sys::console::error_1(&err.into());
+				}
+			});
+		}
+	}) as Box<dyn FnMut()>);
+
+	utils::window()?
+		.add_event_listener_with_callback("online", listener.as_ref().unchecked_ref())?;
 
-const OAUTH_KEY: &str = "http://tools.ietf.org/html/rfc6749#section-4.2";
+	Ok(listener)
+}
 
+#[wasm_bindgen]
 pub struct ClientRemote {
-	webfinger_root_uri: String,
-	username: String,
-	scope: String,
+	user_address: webfinger::UserAddress,
+	webfinger_root_uri: Option<String>,
+	scopes: Vec<scope::Scope>,
 	client_id: String,
-	pub debug: bool, // TODO
-	client: Option<Client>,
+	// prefix of the keys saved by this client
+	cookie_name_header: String,
+	redirect_uri: oauth::RedirectUri,
+	oauth_flow: oauth::OAuthFlow,
+	connect_mode: oauth::ConnectMode,
+	catalog: Option<i18n::Catalog>,
+	settings: RequestSettings,
+	session: std::rc::Rc<Session>,
+	online_listener: Option<Closure<dyn FnMut()>>,
+	channel_listener: Option<ChannelListener>,
+}
+impl Drop for ClientRemote {
+	fn drop(&mut self) {
+		if let (Some(window), Some(listener)) = (web_sys::window(), &self.online_listener) {
+			window
+				.remove_event_listener_with_callback("online", listener.as_ref().unchecked_ref())
+				.ok();
+		}
+		if let Some(channel) = &self.session.channel {
+			channel.set_onmessage(None);
+			channel.close();
+		}
+		// only once it can not be called anymore
+		self.channel_listener.take();
+	}
 }
 impl ClientRemote {
-	pub async fn new(
-		webfinger_root_uri: impl Into<String>,
-		username: impl Into<String>,
-		scope: impl Into<String>,
-		client_id: impl Into<String>,
-		debug: bool,
-	) -> Result<Self, JsValue> {
-		let webfinger_root_uri = webfinger_root_uri.into();
-		let username = username.into();
-		let scope = scope.into();
-		let client_id = client_id.into();
-
-		////////////////////////
+	pub fn builder() -> ClientRemoteBuilder {
+		ClientRemoteBuilder::default()
+	}
+	pub async fn connect(&self) -> Result<bool, Error> {
+		let connected = self.try_mount_saved_client().await?;
 
-		let mut result = Self {
-			webfinger_root_uri,
-			username,
-			scope,
-			client_id,
-			debug,
-			client: None, // TODO
-		};
+		if connected {
+			// the writes of a previous visit may still be waiting
+			if let Err(err) = self.session.replay_outbox().await {
+				web_sys::console::error_1(&err.into());
+			}
+		}
 
-		result.try_mount_saved_client().await?;
+		Ok(connected)
+	}
+	/// Forgets the saved token and unmounts the client, then asks the server
+	/// to revoke the token if it advertises a revocation endpoint.
+	pub async fn disconnect(&self) -> Result<(), Error> {
+		let client = self.session.unmount();
 
-		Ok(result)
+		self.session.forget(client, &self.client_id).await
+	}
+	pub fn on_connection_change(&self, listener: impl Fn(&ConnectionEvent) + 'static) {
+		self.session.listeners.add(std::rc::Rc::new(listener));
+	}
+	/// Listens to the changes of the documents whose path starts with
+	/// `path_prefix` : local writes, new versions found on the server, and
+	/// changes made by the other tabs.
+	pub fn on_change(
+		&self,
+		path_prefix: impl Into<String>,
+		listener: impl Fn(&change::Change) + 'static,
+	) {
+		let path_prefix = path_prefix.into();
+
+		self.session
+			.change_listeners
+			.add(std::rc::Rc::new(move |change: &change::Change| {
+				if change.concerns(&path_prefix) {
+					listener(change);
+				}
+			}));
 	}
 }
 impl ClientRemote {
-	pub fn get_document(
+	pub async fn get_document(
 		&self,
 		path: impl Into<String>,
-		etag: Option<String>, // TODO
-	) -> Result<Promise, JsValue> {
-		match &self.client {
-			Some(client) => client.get_document(path, etag),
-			None => Err(JsValue::from_str("client is not connected")),
-		}
+		if_none_match: Option<String>,
+	) -> Result<DocumentResponse, Error> {
+		let client = self.session.client().await?;
+
+		self.session
+			.checked(client.get_document(path, if_none_match))
+			.await
 	}
-	pub fn put_document(
+	pub async fn put_document(
 		&self,
 		path: impl Into<String>,
 		document: &Document,
-	) -> Result<Promise, JsValue> {
-		match &self.client {
-			Some(client) => client.put_document(path, document),
-			None => Err(JsValue::from_str("client is not connected")),
+		precondition: Precondition,
+	) -> Result<Option<String>, Error> {
+		let path = path.into();
+		self.check_write_access(&path)?;
+
+		self.session
+			.put_document(path, document, precondition)
+			.await
+	}
+	pub async fn delete_document(
+		&self,
+		path: impl Into<String>,
+		if_match: Option<String>,
+	) -> Result<bool, Error> {
+		let path = path.into();
+		self.check_write_access(&path)?;
+
+		self.session.delete_document(path, if_match).await
+	}
+	/// Reads a document like `get_document`, but through the cache when one
+	/// is configured : its `ETag` is sent to the server, and the cached
+	/// version is returned on `304 Not Modified` or when the server can not
+	/// be reached, along with its freshness.
+	pub async fn read_document(
+		&self,
+		path: impl Into<String>,
+	) -> Result<cache::CachedDocument, Error> {
+		self.session.read_document(path.into()).await
+	}
+	/// If the writes made offline are queued, even before being connected.
+	pub fn has_outbox(&self) -> bool {
+		self.session.outbox.is_some()
+	}
+	/// Writes waiting in the outbox, in the order they will be sent.
+	pub async fn pending_writes(&self) -> Result<Vec<outbox::PendingWrite>, Error> {
+		match &self.session.outbox {
+			Some(outbox) => outbox.pending(&self.session.namespace).await,
+			None => Ok(vec![]),
 		}
 	}
+	pub async fn replay_outbox(&self) -> Result<(), Error> {
+		self.session.replay_outbox().await
+	}
+	pub fn get_scopes(&self) -> &[scope::Scope] {
+		&self.scopes
+	}
+	pub(crate) fn document_cache(&self) -> Option<&cache::DocumentCache> {
+		self.session.cache.as_ref()
+	}
+	pub(crate) fn cache_key(&self, path: &str) -> String {
+		self.session.cache_key(path)
+	}
+	/// Removes a document deleted from the server from the cache.
+	pub(crate) async fn forget_cached_document(&self, path: &str) {
+		self.session
+			.store(path, None, change::ChangeOrigin::Remote)
+			.await;
+	}
+	pub async fn list_folder(&self, path: impl Into<String>) -> Result<Folder, Error> {
+		let client = self.session.client().await?;
+
+		self.session.checked(client.list_folder(path)).await
+	}
+	pub async fn get_document_range(
+		&self,
+		path: impl Into<String>,
+		range: std::ops::Range<u64>,
+	) -> Result<Document, Error> {
+		let client = self.session.client().await?;
+
+		self.session
+			.checked(client.get_document_range(path, range))
+			.await
+	}
+	pub fn document_url(&self, path: impl Into<String>) -> Result<String, Error> {
+		self.session.mounted_client()?.document_url(path)
+	}
+	pub fn web_authoring_url(&self, path: impl Into<String>) -> Result<String, Error> {
+		self.session.mounted_client()?.web_authoring_url(path)
+	}
 }
+#[wasm_bindgen]
 impl ClientRemote {
-	fn generate_cookie_name_header(&self) -> String {
-		let webfinger_root_uri_obj = self.webfinger_root_uri.parse::<http::uri::Uri>().unwrap();
-		let client_id_uri_obj = self.client_id.parse::<http::uri::Uri>().unwrap();
-
-		let cookie_name_header = format!(
-			"{}|{}|{}|{}|",
-			match client_id_uri_obj.port() {
-				Some(port) => format!("{}:{}", client_id_uri_obj.host().unwrap(), port),
-				None => String::from(client_id_uri_obj.host().unwrap()),
-			},
-			self.username,
-			match webfinger_root_uri_obj.port() {
-				Some(port) => format!("{}:{}", webfinger_root_uri_obj.host().unwrap(), port),
-				None => String::from(webfinger_root_uri_obj.host().unwrap()),
-			},
-			self.scope
-		);
-		let cookie_name_header =
-			pct_str::PctString::encode(cookie_name_header.chars(), pct_str::URIReserved);
+	#[wasm_bindgen(js_name = getDocument)]
+	pub fn get_document_promise(&self, path: String, if_none_match: Option<String>) -> Promise {
+		let session = self.session.clone();
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			let client = session.client().await?;
+			let response = session
+				.checked(client.get_document(path, if_none_match))
+				.await?;
+
+			Ok(serde_wasm_bindgen::to_value(&response)?)
+		})
+	}
+	/// Without `if_match`, the document is overwritten, unless `create_only`.
+	#[wasm_bindgen(js_name = putDocument)]
+	pub fn put_document_promise(
+		&self,
+		path: String,
+		document: JsValue,
+		if_match: Option<String>,
+		create_only: Option<bool>,
+	) -> Promise {
+		let session = self.session.clone();
+		let access = self.check_write_access(&path);
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			access?;
+			let document: Document = serde_wasm_bindgen::from_value(document)?;
+			let precondition = match (if_match, create_only) {
+				(Some(etag), _) => Precondition::IfMatch(etag),
+				(None, Some(true)) => Precondition::CreateOnly,
+				(None, _) => Precondition::Unconditional,
+			};
+			let etag = session.put_document(path, &document, precondition).await?;
+
+			Ok(etag.map(JsValue::from).unwrap_or(JsValue::NULL))
+		})
+	}
+	#[wasm_bindgen(js_name = deleteDocument)]
+	pub fn delete_document_promise(&self, path: String, if_match: Option<String>) -> Promise {
+		let session = self.session.clone();
+		let access = self.check_write_access(&path);
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			access?;
+			let existed = session.delete_document(path, if_match).await?;
+
+			Ok(JsValue::from_bool(existed))
+		})
+	}
+	#[wasm_bindgen(js_name = readDocument)]
+	pub fn read_document_promise(&self, path: String) -> Promise {
+		let session = self.session.clone();
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			let cached = session.read_document(path).await?;
+
+			Ok(serde_wasm_bindgen::to_value(&cached)?)
+		})
+	}
+	#[wasm_bindgen(js_name = listFolder)]
+	pub fn list_folder_promise(&self, path: String) -> Promise {
+		let session = self.session.clone();
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			let client = session.client().await?;
+			let folder = session.checked(client.list_folder(path)).await?;
+
+			Ok(serde_wasm_bindgen::to_value(&folder)?)
+		})
+	}
+	#[wasm_bindgen(js_name = isConnected)]
+	pub fn is_connected_js(&self) -> bool {
+		self.is_connected()
+	}
+	#[wasm_bindgen(js_name = disconnect)]
+	pub fn disconnect_promise(&self) -> Promise {
+		let client = self.session.unmount();
+		let session = self.session.clone();
+		let client_id = self.client_id.clone();
+
+		wasm_bindgen_futures::future_to_promise(async move {
+			session.forget(client, &client_id).await?;
+
+			Ok(JsValue::UNDEFINED)
+		})
+	}
+	#[wasm_bindgen(js_name = onConnectionChange)]
+	pub fn on_connection_change_js(&self, callback: js_sys::Function) {
+		self.on_connection_change(move |event| {
+			if let Err(err) = callback.call1(&JsValue::NULL, &JsValue::from_str(event.as_str())) {
+				web_sys::console::error_1(&err);
+			}
+		});
+	}
+	#[wasm_bindgen(js_name = onChange)]
+	pub fn on_change_js(&self, path_prefix: String, callback: js_sys::Function) {
+		self.on_change(path_prefix, move |change| {
+			let result = serde_wasm_bindgen::to_value(change)
+				.map_err(JsValue::from)
+				.and_then(|change| callback.call1(&JsValue::NULL, &change));
+			if let Err(err) = result {
+				web_sys::console::error_1(&err);
+			}
+		});
+	}
+}
+impl ClientRemote {
+	fn check_write_access(&self, path: &str) -> Result<(), Error> {
+		if scope::Scope::can_write(&self.scopes, path) {
+			Ok(())
+		} else {
+			Err(Error::OutOfScope {
+				path: String::from(path),
+			})
+		}
+	}
+	fn pending_authorization_key(&self) -> String {
+		format!("{}oauth_request", self.cookie_name_header)
+	}
+	// The pending authorization only has to survive the redirection to the
+	// OAuth server, so it is kept in the `sessionStorage` of the tab, whatever
+	// the token store.
+	fn save_pending_authorization(
+		&self,
+		pending: &oauth::PendingAuthorization,
+	) -> Result<(), Error> {
+		session_storage()?.set_item(
+			&self.pending_authorization_key(),
+			&serde_json::to_string(pending).unwrap_or_default(),
+		)?;
+
+		Ok(())
+	}
+	fn return_path_key(&self) -> String {
+		format!("{}return_to", self.cookie_name_header)
+	}
+	// The page (and its query) the user was on, to be restored after the
+	// round-trip to the OAuth server.
+	fn save_return_path(&self, location: &web_sys::Location) -> Result<(), Error> {
+		session_storage()?.set_item(
+			&self.return_path_key(),
+			&format!(
+				"{}{}{}",
+				location.pathname()?,
+				location.search()?,
+				location.hash()?
+			),
+		)?;
+
+		Ok(())
+	}
+	fn take_return_path(&self) -> Result<Option<String>, Error> {
+		let storage = session_storage()?;
+		let key = self.return_path_key();
+
+		let return_path = storage.get_item(&key)?;
+		storage.remove_item(&key)?;
 
-		cookie_name_header.to_string()
+		// only paths of this origin are restored
+		Ok(return_path.filter(|path| path.starts_with('/') && !path.starts_with("//")))
+	}
+	// The response has to be the answer of our own authorization request.
+	fn take_pending_authorization(
+		&self,
+		state: Option<&str>,
+	) -> Result<oauth::PendingAuthorization, Error> {
+		let storage = session_storage()?;
+		let key = self.pending_authorization_key();
+
+		let pending = storage.get_item(&key)?;
+		storage.remove_item(&key)?;
+
+		match pending
+			.and_then(|pending| serde_json::from_str::<oauth::PendingAuthorization>(&pending).ok())
+		{
+			Some(pending) if state == Some(pending.state.as_str()) => Ok(pending),
+			_ => Err(Error::OAuthStateMismatch),
+		}
 	}
-	async fn try_get_webfinger_data(&self) -> Result<WebfingerResponse, JsValue> {
+	async fn try_get_webfinger_data(&self) -> Result<webfinger::WebfingerResponse, Error> {
 		let mut opts = web_sys::RequestInit::new();
 		opts.method("GET");
 		opts.mode(web_sys::RequestMode::Cors);
 
-		let webfinger_uri = self
-			.webfinger_root_uri
-			.strip_suffix('/')
-			.unwrap_or(&self.webfinger_root_uri);
+		let url = self
+			.user_address
+			.webfinger_url(self.webfinger_root_uri.as_deref());
 
-		let webfinger_root_uri_obj = self.webfinger_root_uri.parse::<http::uri::Uri>().unwrap();
+		let request = web_sys::Request::new_with_str_and_init(&url, &opts)?;
 
-		let url = format!(
-			"{webfinger_uri}/.well-known/webfinger?resource=acct:{}@{}",
-			self.username,
-			webfinger_root_uri_obj.host().unwrap()
-		);
+		let resp = fetch(&request, &self.settings).await?;
+		if !resp.ok() {
+			return Err(Error::Webfinger(format!(
+				"error {} when requesting `{}`",
+				resp.status(),
+				url
+			)));
+		}
+		let json = wasm_bindgen_futures::JsFuture::from(resp.text()?)
+			.await
+			.map_err(|err| Error::Webfinger(js_error_message(&err)))?;
+		let response: webfinger::WebfingerResponse =
+			serde_json::from_str(&json.as_string().unwrap_or_default())
+				.map_err(|err| Error::Webfinger(format!("{}", err)))?;
 
-		let request = web_sys::Request::new_with_str_and_init(&url, &opts)?;
+		Ok(response)
+	}
+	async fn try_get_storage_link(&self) -> Result<webfinger::Link, Error> {
+		let webfinger = self.try_get_webfinger_data().await?;
 
-		let window = web_sys::window().ok_or_else(|| JsValue::from_str("window not found"))?;
+		webfinger.try_storage_link(&self.user_address).cloned()
+	}
+	async fn try_mount_saved_client(&self) -> Result<bool, Error> {
+		let window = utils::window()?;
+
+		let response = self
+			.complete_authorization(&window.location().hash()?, &window.location().search()?)
+			.await;
+
+		if !matches!(response, Ok(None)) {
+			// hide token, code (or error) from URL, and go back where the user was
+			let return_path = match self.take_return_path()? {
+				Some(return_path) => return_path,
+				None => window.location().pathname()?,
+			};
+			window.history()?.replace_state_with_url(
+				&String::new().into(),
+				"",
+				Some(&return_path),
+			)?;
+		}
 
-		let resp_value =
-			wasm_bindgen_futures::JsFuture::from(window.fetch_with_request(&request)).await?; // TODO : try to remove await
-		let resp: web_sys::Response = resp_value.dyn_into()?;
-		let json = wasm_bindgen_futures::JsFuture::from(resp.json()?).await?; // TODO : try to remove await
-		let response: WebfingerResponse = json.into_serde().unwrap();
+		let (token, link) = match response? {
+			Some(response) => response,
+			None => match self
+				.session
+				.token_store
+				.load(&self.session.token_key)
+				.await?
+			{
+				Some(token) => (token_store::StoredToken::parse(&token), None),
+				None => return Ok(false),
+			},
+		};
 
-		Ok(response)
+		self.mount_token(token, link).await
 	}
-	async fn try_mount_saved_client(&mut self) -> Result<bool, JsValue> {
-		let window = web_sys::window().ok_or_else(|| JsValue::from_str("window not found"))?;
-		let document = window
-			.document()
-			.ok_or_else(|| JsValue::from_str("document not found"))?;
-		let document = document
-			.dyn_ref::<web_sys::HtmlDocument>()
-			.ok_or_else(|| JsValue::from_str("document can not be casted into HtmlDocument"))?;
-
-		let hash = window.location().hash()?;
-
-		let access_token = if hash.contains("token_type") && ACCESS_TOKEN_REGEX.is_match(&hash) {
-			if let Some(matches) = ACCESS_TOKEN_REGEX.captures_iter(&hash).next() {
-				matches.get(1).map(|access_token| {
-					pct_str::PctString::new(access_token.as_str())
-						.unwrap()
-						.decode()
-				})
-			} else {
-				None
-			}
+	// Turns the response of the OAuth server (from the redirect URI) into a
+	// saved token, if any.
+	async fn complete_authorization(
+		&self,
+		hash: &str,
+		search: &str,
+	) -> Result<Option<(token_store::StoredToken, Option<webfinger::Link>)>, Error> {
+		let mut link = None;
+
+		let token = if let Some(response) = oauth::parse_fragment(hash) {
+			self.take_pending_authorization(match &response {
+				Ok(token) => token.get_state(),
+				Err(err) => err.get_state(),
+			})?;
+			let response = response.map_err(Error::OAuth)?;
+
+			token_store::StoredToken::from_response(&response)
+		} else if let (oauth::OAuthFlow::AuthorizationCode, Some(response)) =
+			(self.oauth_flow, oauth::parse_query(search))
+		{
+			let pending = self.take_pending_authorization(match &response {
+				Ok(code) => code.get_state(),
+				Err(err) => err.get_state(),
+			})?;
+			let response = response.map_err(Error::OAuth)?;
+
+			let storage_link = self.try_get_storage_link().await?;
+			let capabilities = webfinger::StorageCapabilities::from_link(&storage_link);
+			let endpoint = capabilities
+				.get_token_endpoint()
+				.ok_or(Error::Unsupported(webfinger::TOKEN_ENDPOINT_KEY))?;
+
+			let response = request_token(
+				endpoint,
+				&[
+					("grant_type", "authorization_code"),
+					("code", response.get_code()),
+					("redirect_uri", &pending.redirect_uri),
+					("client_id", &self.client_id),
+					(
+						"code_verifier",
+						pending.code_verifier.as_deref().unwrap_or_default(),
+					),
+				],
+				&self.settings,
+			)
+			.await?;
+			link = Some(storage_link);
+
+			token_store::StoredToken::from_response(&response)
 		} else {
-			None
+			return Ok(None);
 		};
 
-		let access_token = match access_token {
-			Some(access_token) => {
-				// hide token from URL
-				window
-					.history()?
-					.replace_state_with_url(&String::new().into(), "", Some("/"))?;
+		self.session
+			.token_store
+			.save(&self.session.token_key, &token.serialize())
+			.await?;
 
-				document
-					.set_cookie(&format!(
-						"{}access_token={}",
-						self.generate_cookie_name_header(),
-						pct_str::PctString::encode(access_token.chars(), pct_str::URIReserved)
-					))
-					.unwrap();
+		Ok(Some((token, link)))
+	}
+	async fn mount_token(
+		&self,
+		token: token_store::StoredToken,
+		link: Option<webfinger::Link>,
+	) -> Result<bool, Error> {
+		let token_key = &self.session.token_key;
+		let token_store = &self.session.token_store;
 
-				Some(access_token)
-			}
-			None => {
-				let document = window
-					.document()
-					.ok_or_else(|| JsValue::from_str("document not found"))?;
-				let document = document.dyn_ref::<web_sys::HtmlDocument>().unwrap();
-
-				let all_cookies = document.cookie()?;
-
-				let mut access_token = None;
-				for cookie in all_cookies.split(';') {
-					let mut iter = cookie.split('=');
-					let name = iter.next().map(str::trim);
-					let value = iter
-						.next()
-						.map(|res| pct_str::PctString::new(res.trim()).unwrap().decode());
-
-					if let Some(name) = name {
-						if name == format!("{}access_token", self.generate_cookie_name_header()) {
-							access_token = value;
-						}
-					}
-				}
+		if token.is_expired() && token.get_refresh_token().is_none() {
+			token_store.remove(token_key).await?;
+
+			return Ok(false);
+		}
 
-				access_token
+		// the link is saved along with the token, in order to mount it offline
+		let token = match (link, token.get_storage_link()) {
+			(None, Some(_)) => token,
+			(link, _) => {
+				let link = match link {
+					Some(link) => link,
+					None => self.try_get_storage_link().await?,
+				};
+				let token = token.with_storage_link(Some(link));
+				token_store.save(token_key, &token.serialize()).await?;
+
+				token
 			}
 		};
 
-		match access_token {
-			Some(access_token) => {
-				let webfinger = self.try_get_webfinger_data().await?; // TODO : try to remove await
-
-				let server_path = webfinger.links.get(0).map(|link| link.href.clone());
-
-				match server_path {
-					Some(server_path) => {
-						let mut opts = web_sys::RequestInit::new();
-						opts.method("HEAD");
-						opts.mode(web_sys::RequestMode::Cors);
-
-						let subfolder = self.scope.split(':').next().unwrap(); // TODO
-
-						let full_path = format!("{}/{}/", server_path, subfolder);
-
-						let request =
-							web_sys::Request::new_with_str_and_init(&full_path, &opts).unwrap();
-						request
-							.headers()
-							.set("Authorization", &format!("Bearer {}", access_token))?;
-
-						let window = web_sys::window().ok_or("window not found")?;
-
-						let root_head = wasm_bindgen_futures::JsFuture::from(Promise::new(
-							&mut |resolve, reject| {
-								let reject = std::sync::Arc::new(reject);
-
-								let reject_for_main = reject.clone();
-								let process_callback =
-									Closure::once(Box::new(move |resp: JsValue| {
-										let resp: web_sys::Response = resp.dyn_into().unwrap();
-
-										if resp.ok() {
-											resolve
-												.call1(&JsValue::NULL, &JsValue::from_bool(true))
-												.unwrap();
-										} else {
-											resolve
-												.call1(&JsValue::NULL, &JsValue::from_bool(false))
-												.unwrap();
-										}
-									}) as Box<dyn FnOnce(JsValue)>);
-
-								let err_callback = Closure::wrap(Box::new(move |err: JsValue| {
-									reject
-										.call1(&JsValue::NULL, &format!("{:?}", err).into())
-										.unwrap();
-								}) as Box<dyn FnMut(JsValue)>);
-
-								window
-									.fetch_with_request(&request)
-									.then(&process_callback)
-									.catch(&err_callback);
-
-								process_callback.forget();
-								err_callback.forget();
-							},
-						))
-						.await?;
+		let mut client = match Client::from_token(token, &self.settings) {
+			Some(client) => client,
+			None => return Ok(false),
+		};
 
-						let root_head = root_head.as_bool();
+		if client.token.is_expired() {
+			match client.refresh(&self.client_id).await {
+				Ok(refreshed) => {
+					token_store
+						.save(token_key, &refreshed.token.serialize())
+						.await?;
+					client = refreshed;
+				}
+				// refreshed by the first request once back online
+				Err(Error::Network(_) | Error::Timeout(_)) => {
+					self.session.mount(client);
 
-						if let Some(true) = root_head {
-							self.client = Some(Client {
-								access_token,
-								server_path,
-								debug: self.debug,
-							});
+					return Ok(true);
+				}
+				Err(_) => {
+					token_store.remove(token_key).await?;
 
-							Ok(true)
-						} else {
-							Ok(false)
-						}
-					}
-					None => Err(JsValue::from_str(
-						"can not find `links` content in webfinger reponse of the server",
-					)),
+					return Ok(false);
 				}
 			}
-			None => Ok(false),
+		}
+
+		// the first scope is enough to check that the token is still accepted ;
+		// its folder does not exist until a document is written in it
+		let request = client.build_request("HEAD", &self.scopes[0].folder(), None)?;
+		match client
+			.send(&request)
+			.await
+			.map(|root_head| root_head.status())
+		{
+			// offline, the token is checked by the next requests
+			Ok(200..=299 | 404) | Err(Error::Network(_) | Error::Timeout(_)) => {
+				self.session.mount(client);
+
+				Ok(true)
+			}
+			Ok(401 | 403) => {
+				// the token is not accepted anymore
+				token_store.remove(token_key).await?;
+
+				Ok(false)
+			}
+			Ok(_) => Ok(false),
+			Err(err) => Err(err),
 		}
 	}
 }
 impl ClientRemote {
-	pub async fn show_connect_overlay(&self) -> Result<(), JsValue> {
-		let webfinger = self.try_get_webfinger_data().await?; // TODO : try to remove await
-
-		match webfinger.links.get(0) {
-			Some(link) => {
-				let window =
-					web_sys::window().ok_or_else(|| JsValue::from_str("window not found"))?;
-				let document = window
-					.document()
-					.ok_or_else(|| JsValue::from_str("document not found"))?;
-
-				let oauth_origin = link.properties.get(OAUTH_KEY).unwrap().as_ref().unwrap();
-				let oauth_path = format!(
-					"{oauth_origin}?redirect_uri={}&scope={}&client_id={}&response_type={}",
-					pct_str::PctString::encode(
-						format!("{}", window.location().to_string()).chars(),
-						pct_str::URIReserved
-					), // TODO : change to base url (no page name, or its arguments)
-					pct_str::PctString::encode(self.scope.chars(), pct_str::URIReserved),
-					pct_str::PctString::encode(self.client_id.chars(), pct_str::URIReserved),
-					pct_str::PctString::encode("token".chars(), pct_str::URIReserved),
-				);
-
-				// window.location().set_href(&oauth_path).unwrap();
-
-				let next_window = document.create_element("div")?;
-				next_window.set_attribute("id", "pontus_onyx_oauth_next_window")?;
-				let next_window = next_window.dyn_ref::<web_sys::HtmlElement>().unwrap();
-
-				next_window
-					.style()
-					.set_property("border", "5px solid #FF4B03")?;
-				next_window.style().set_property("background", "white")?;
-				next_window.style().set_property("padding", "1em")?;
-				next_window.style().set_property("text-align", "center")?;
-				next_window.style().set_property("position", "absolute")?;
-				next_window.style().set_property("width", "50%")?;
-				next_window.style().set_property("height", "50%")?;
-				next_window.style().set_property("left", "25%")?;
-				next_window.style().set_property("top", "25%")?;
-				next_window.style().set_property("opacity", "0.8")?;
-
-				let svg = document.create_element("svg")?;
-				svg.set_inner_html(include_str!("remoteStorage.svg"));
-				let svg = svg.dyn_ref::<web_sys::HtmlElement>().unwrap();
-				svg.set_attribute("width", "50")?;
-				svg.set_attribute("height", "50")?;
-				next_window.append_child(svg)?;
-
-				let explain = document.create_element("p")?;
-				explain.set_inner_html(
-					&format!(
-						r#"You will be temporary redirected to<br><a href="{}">{}</a><br>in order to authenticate on the requested remoteStorage server, then bring back to this page."#,
-						oauth_path,
-						oauth_origin
-					)
-				);
-
-				next_window.append_child(&explain)?;
-
-				let p_buttons = document.create_element("p")?;
-
-				let abort = document.create_element("button")?;
-				let abort = abort.dyn_ref::<web_sys::HtmlElement>().unwrap();
-				abort.style().set_property("width", "40%")?;
-				abort.style().set_property("height", "5em")?;
-				abort.style().set_property("border", "2px solid #FF4B03")?;
-				abort.style().set_property("background", "white")?;
-				abort.style().set_property("cursor", "pointer")?;
-				abort.style().set_property("font-weight", "bold")?;
-				abort.set_inner_html("❌ Abort");
-				let close_next_window = wasm_bindgen::closure::Closure::wrap(Box::new(move || {
-					if let Some(window) = web_sys::window() {
-						if let Some(document) = window.document() {
-							if let Some(body) = document.body() {
-								if let Some(node) =
-									document.get_element_by_id("pontus_onyx_oauth_next_window")
-								{
-									body.remove_child(&node).ok();
-								}
-							}
-						}
-					}
-				})
-					as Box<dyn FnMut()>);
-				abort.set_onclick(Some(close_next_window.as_ref().unchecked_ref()));
-				close_next_window.forget();
+	// Saves what is needed to check the response of the OAuth server, then
+	// returns the URL of the authorization request and the OAuth endpoint.
+	async fn prepare_authorization(&self) -> Result<(String, String), Error> {
+		let link = self.try_get_storage_link().await?;
+
+		let window = utils::window()?;
+
+		let capabilities = webfinger::StorageCapabilities::from_link(&link);
+		let oauth_origin = capabilities.get_auth_endpoint().ok_or_else(|| {
+			Error::Webfinger(format!(
+				"the remoteStorage link does not advertise any OAuth endpoint (`{}` property)",
+				webfinger::OAUTH_KEY
+			))
+		})?;
+		let code_verifier = match self.oauth_flow {
+			oauth::OAuthFlow::Implicit => None,
+			oauth::OAuthFlow::AuthorizationCode => {
+				if capabilities.get_token_endpoint().is_none() {
+					return Err(Error::Unsupported(webfinger::TOKEN_ENDPOINT_KEY));
+				}
+
+				Some(oauth::random_token(32)?)
+			}
+		};
+		let pending = oauth::PendingAuthorization {
+			state: oauth::random_token(16)?,
+			redirect_uri: match (&self.redirect_uri, self.connect_mode) {
+				(oauth::RedirectUri::Explicit(redirect_uri), _) => redirect_uri.clone(),
+				(_, oauth::ConnectMode::Popup) => {
+					format!("{}{}", window.location().origin()?, POPUP_CALLBACK_PATH)
+				}
+				(redirect_uri, oauth::ConnectMode::Redirect) => {
+					redirect_uri.resolve(&window.location())?
+				}
+			},
+			code_verifier,
+		};
+		self.save_pending_authorization(&pending)?;
+		if let oauth::ConnectMode::Redirect = self.connect_mode {
+			self.save_return_path(&window.location())?;
+		}
+
+		let mut oauth_path = format!(
+			"{oauth_origin}?redirect_uri={}&scope={}&client_id={}&response_type={}&state={}",
+			pct_str::PctString::encode(pending.redirect_uri.chars(), pct_str::URIReserved),
+			pct_str::PctString::encode(
+				scope::Scope::serialize_list(&self.scopes).chars(),
+				pct_str::URIReserved
+			),
+			pct_str::PctString::encode(self.client_id.chars(), pct_str::URIReserved),
+			pct_str::PctString::encode(
+				self.oauth_flow.response_type().chars(),
+				pct_str::URIReserved
+			),
+			pct_str::PctString::encode(pending.state.chars(), pct_str::URIReserved),
+		);
+		if let Some(code_verifier) = &pending.code_verifier {
+			oauth_path += &format!(
+				"&code_challenge={}&code_challenge_method=S256",
+				oauth::code_challenge(code_verifier).await?
+			);
+		}
+
+		Ok((oauth_path, String::from(oauth_origin)))
+	}
+	/// Displays a `ConnectWidget` over the whole page, in a `.rs-overlay`
+	/// element, until the user connects or aborts.
+	pub async fn show_connect_overlay(&self) -> Result<(), Error> {
+		let document = utils::document()?;
+		let body = document
+			.body()
+			.ok_or_else(|| Error::Js(String::from("body not found")))?;
+
+		let overlay = document.create_element("div")?;
+		overlay.set_class_name("rs-overlay");
+		body.append_child(&overlay)?;
+
+		let widget = widget::ConnectWidget::mount(&overlay)?;
+		if let Some(catalog) = &self.catalog {
+			widget.set_catalog(catalog.clone())?;
+		}
+		let mut result = self.show_connect_widget(&widget).await;
 
-				p_buttons.append_child(abort)?;
+		// the error is displayed until the user tries again or aborts
+		while result.is_err() {
+			match next_address(&widget).await {
+				Ok(Some(address)) if address == self.user_address => {
+					result = self.show_connect_widget(&widget).await;
+				}
+				Ok(Some(_)) => widget.set_state(self.other_address_state(&widget))?,
+				Ok(None) => break,
+				Err(err) => {
+					result = Err(err);
+					break;
+				}
+			}
+		}
+		overlay.remove();
 
-				let a_next = document.create_element("a")?;
-				a_next.set_attribute("href", &oauth_path)?;
+		result
+	}
+	fn other_address_state(&self, widget: &widget::ConnectWidget) -> widget::WidgetState {
+		widget::WidgetState::Error(widget.get_catalog().format(
+			i18n::MessageKey::OtherAddress,
+			&[&self.user_address.to_string()],
+		))
+	}
+	/// Asks the user to authorize this app through `widget`, until the user
+	/// connects or aborts.
+	///
+	/// In the redirect connect mode, the page is left when the user accepts.
+	pub async fn show_connect_widget(&self, widget: &widget::ConnectWidget) -> Result<(), Error> {
+		let result = self.authorize_with_widget(widget).await;
+
+		match &result {
+			Ok(()) => widget.set_state(match self.is_connected() {
+				true => widget::WidgetState::Connected {
+					address: self.user_address.to_string(),
+				},
+				false => widget::WidgetState::Disconnected,
+			})?,
+			Err(err) => {
+				widget.set_state(widget::WidgetState::Error(widget.get_catalog().error(err)))?
+			}
+		}
 
-				let button_next = document.create_element("button")?;
-				let button_next = button_next.dyn_ref::<web_sys::HtmlElement>().unwrap();
-				button_next.set_inner_html("Next &gt;");
-				button_next.style().set_property("width", "40%")?;
-				button_next.style().set_property("height", "5em")?;
-				button_next.style().set_property("margin-left", "10%")?;
-				button_next
-					.style()
-					.set_property("border", "2px solid black")?;
-				button_next.style().set_property("background", "#FF4B03")?;
-				button_next.style().set_property("cursor", "pointer")?;
-				button_next.style().set_property("font-weight", "bold")?;
-				a_next.append_child(button_next)?;
+		result
+	}
+	async fn authorize_with_widget(&self, widget: &widget::ConnectWidget) -> Result<(), Error> {
+		let window = utils::window()?;
+
+		let (oauth_path, oauth_origin) = self.prepare_authorization().await?;
+		let popup = self.connect_mode == oauth::ConnectMode::Popup;
+
+		widget.set_state(widget::WidgetState::Authorizing {
+			auth_url: oauth_path.clone(),
+			server: oauth_origin,
+			popup,
+		})?;
+
+		// settled with the URL of the callback page, or `null` when aborted
+		let mut resolve = None;
+		let response = Promise::new(&mut |resolve_fn, _| resolve = Some(resolve_fn));
+		let resolve = resolve.ok_or_else(|| Error::Js(String::from("can not create a Promise")))?;
+
+		let action_resolve = resolve.clone();
+		let listener = widget.on_action(move |action| match action {
+			widget::WidgetAction::Abort => {
+				action_resolve.call1(&JsValue::NULL, &JsValue::NULL).ok();
+			}
+			widget::WidgetAction::Authorize(event) if popup => {
+				if let Some(window) = web_sys::window() {
+					// when the popup is blocked, the link still redirects the whole page
+					if let Ok(Some(_)) = window.open_with_url_and_target_and_features(
+						&oauth_path,
+						POPUP_WINDOW_NAME,
+						"popup,width=600,height=700",
+					) {
+						event.prevent_default();
+					}
+				}
+			}
+			_ => {}
+		});
 
-				p_buttons.append_child(&a_next)?;
+		let result = self.wait_popup_response(&window, resolve, response).await;
 
-				next_window.append_child(&p_buttons)?;
+		widget.remove_action_listener(listener);
 
-				document.body().unwrap().append_child(next_window)?;
+		result
+	}
+	async fn wait_popup_response(
+		&self,
+		window: &web_sys::Window,
+		resolve: js_sys::Function,
+		response: Promise,
+	) -> Result<(), Error> {
+		let origin = window.location().origin()?;
+		let on_message = Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+			// only the callback page, hosted along with this app, is trusted
+			if event.origin() != origin {
+				return;
+			}
 
-				// TODO : automatic redirection ?
+			let data = event.data();
+			let message_type = js_sys::Reflect::get(&data, &JsValue::from_str("type"))
+				.ok()
+				.and_then(|message_type| message_type.as_string());
+			let url = js_sys::Reflect::get(&data, &JsValue::from_str("url"))
+				.ok()
+				.filter(JsValue::is_string);
 
-				Ok(())
+			if let (Some(POPUP_MESSAGE_TYPE), Some(url)) = (message_type.as_deref(), url) {
+				resolve.call1(&JsValue::NULL, &url).ok();
 			}
-			None => Err(JsValue::from_str(
-				"can not find `links` content in webfinger reponse of the server",
-			)),
+		}) as Box<dyn FnMut(web_sys::MessageEvent)>);
+		window.add_event_listener_with_callback("message", on_message.as_ref().unchecked_ref())?;
+
+		let url = wasm_bindgen_futures::JsFuture::from(response).await;
+
+		window
+			.remove_event_listener_with_callback("message", on_message.as_ref().unchecked_ref())?;
+		drop(on_message);
+
+		let url = match url?.as_string() {
+			Some(url) => url,
+			None => return Ok(()), // aborted by the user
+		};
+
+		let (url, hash) = url.split_once('#').unwrap_or((&url, ""));
+		let search = url
+			.split_once('?')
+			.map(|(_, search)| search)
+			.unwrap_or_default();
+
+		let (token, link) = self
+			.complete_authorization(hash, search)
+			.await?
+			.ok_or_else(|| {
+				Error::MalformedResponse(String::from(
+					"the OAuth callback page did not receive any response",
+				))
+			})?;
+
+		self.mount_token(token, link).await?;
+
+		Ok(())
+	}
+	/// Keeps `widget` in sync with the connection of `remote`, and handles its
+	/// actions.
+	///
+	/// Only the address `remote` has been built with can be authorized, an
+	/// error is displayed for the other ones : the app has to listen to
+	/// `WidgetAction::AddressSubmitted` itself in order to build a client for
+	/// them.
+	pub fn bind_widget(
+		remote: &std::rc::Rc<ClientRemote>,
+		widget: &widget::ConnectWidget,
+	) -> Result<(), Error> {
+		if let Some(catalog) = &remote.catalog {
+			widget.set_catalog(catalog.clone())?;
 		}
+		widget.set_address(Some(remote.user_address.clone()))?;
+		widget.set_state(match remote.is_connected() {
+			true => widget::WidgetState::Connected {
+				address: remote.user_address.to_string(),
+			},
+			false => widget::WidgetState::Disconnected,
+		})?;
+
+		let address = remote.user_address.to_string();
+		let state_widget = widget.clone();
+		remote.on_connection_change(move |event| {
+			let state = match event {
+				ConnectionEvent::Connected => widget::WidgetState::Connected {
+					address: address.clone(),
+				},
+				ConnectionEvent::Disconnected => widget::WidgetState::Disconnected,
+				ConnectionEvent::ReauthRequired => widget::WidgetState::Error(String::from(
+					state_widget
+						.get_catalog()
+						.get(i18n::MessageKey::SessionExpired),
+				)),
+			};
+			state_widget.set_state(state).ok();
+		});
+
+		// weak references avoid the cycle between the client and the widget
+		let weak_remote = std::rc::Rc::downgrade(remote);
+		let weak_widget = widget.downgrade();
+		widget.on_action(move |action| {
+			let (remote, widget) = match (weak_remote.upgrade(), weak_widget.upgrade()) {
+				(Some(remote), Some(widget)) => (remote, widget),
+				_ => return,
+			};
+
+			match action {
+				widget::WidgetAction::AddressSubmitted(address)
+					if address == &remote.user_address =>
+				{
+					wasm_bindgen_futures::spawn_local(async move {
+						// errors are displayed by the widget
+						remote.show_connect_widget(&widget).await.ok();
+					});
+				}
+				widget::WidgetAction::AddressSubmitted(_) => {
+					widget.set_state(remote.other_address_state(&widget)).ok();
+				}
+				widget::WidgetAction::Abort if !remote.is_connected() => {
+					widget.set_state(widget::WidgetState::Disconnected).ok();
+				}
+				widget::WidgetAction::Disconnect => {
+					wasm_bindgen_futures::spawn_local(async move {
+						if let Err(err) = remote.disconnect().await {
+							let text = widget.get_catalog().error(&err);
+							widget.set_state(widget::WidgetState::Error(text)).ok();
+						}
+					});
+				}
+				_ => {}
+			}
+		});
+
+		Ok(())
 	}
 }
 impl ClientRemote {
 	pub fn is_connected(&self) -> bool {
-		self.client.is_some()
+		self.session.client.borrow().is_some()
+	}
+	pub fn get_capabilities(&self) -> Option<webfinger::StorageCapabilities> {
+		self.session
+			.client
+			.borrow()
+			.as_ref()
+			.map(|client| client.get_capabilities().clone())
 	}
 }
 
+#[derive(Clone)]
 pub struct Client {
 	server_path: String,
-	access_token: String,
-	pub debug: bool, // TODO
+	token: token_store::StoredToken,
+	capabilities: webfinger::StorageCapabilities,
+	settings: RequestSettings,
 }
 impl Client {
-	pub fn get_document(
+	// `None` if the storage link has not been saved with the token
+	fn from_token(token: token_store::StoredToken, settings: &RequestSettings) -> Option<Self> {
+		let link = token.get_storage_link()?;
+
+		Some(Self {
+			server_path: String::from(link.get_href()?),
+			capabilities: webfinger::StorageCapabilities::from_link(link),
+			settings: settings.clone(),
+			token,
+		})
+	}
+	async fn send(&self, request: &web_sys::Request) -> Result<web_sys::Response, Error> {
+		fetch(request, &self.settings).await
+	}
+	pub fn get_capabilities(&self) -> &webfinger::StorageCapabilities {
+		&self.capabilities
+	}
+	fn build_request(
 		&self,
-		path: impl Into<String>,
-		etag: Option<String>, // TODO
-	) -> Result<Promise, JsValue> {
-		let path = path.into();
-
+		method: &str,
+		path: &str,
+		body: Option<&JsValue>,
+	) -> Result<web_sys::Request, Error> {
 		let mut opts = web_sys::RequestInit::new();
-		opts.method("GET");
+		opts.method(method);
+		opts.body(body);
 		opts.mode(web_sys::RequestMode::Cors);
 
 		let full_path = format!("{}{}", self.server_path, path);
 
-		let request = web_sys::Request::new_with_str_and_init(&full_path, &opts).unwrap();
-		request
-			.headers()
-			.set("Authorization", &format!("Bearer {}", self.access_token))?;
-
-		let window = web_sys::window().ok_or("window not found")?;
-
-		Ok(Promise::new(&mut |resolve, reject| {
-			let reject = std::sync::Arc::new(reject);
-
-			let reject_for_main = reject.clone();
-			let process_callback = Closure::once(Box::new(move |resp: JsValue| {
-				let resp: web_sys::Response = resp.dyn_into().unwrap();
-
-				if resp.ok() {
-					let headers = resp.headers();
-
-					let etag = headers.get("etag");
-					if etag.is_err() {
-						reject_for_main
-							.call1(
-								&JsValue::NULL,
-								&JsValue::from_str(
-									"can not get `Etag` header from server response",
-								),
-							)
-							.unwrap();
-					}
-					let etag = etag.unwrap();
-
-					let content_type = headers.get("content-type");
-					if content_type.is_err() {
-						reject_for_main
-							.call1(
-								&JsValue::NULL,
-								&JsValue::from_str(
-									"can not get `Content-Type` header from server response",
-								),
-							)
-							.unwrap();
-					}
-					let content_type = content_type.unwrap();
-					if content_type.is_none() {
-						reject_for_main
-							.call1(
-								&JsValue::NULL,
-								&JsValue::from_str(
-									"missing `Content-Type` header from server response",
-								),
-							)
-							.unwrap();
-					}
-					let content_type = content_type.unwrap();
+		let request = web_sys::Request::new_with_str_and_init(&full_path, &opts)?;
+		request.headers().set(
+			"Authorization",
+			&format!("Bearer {}", self.token.get_access_token()),
+		)?;
 
-					let body_process = Closure::once(Box::new(move |body: JsValue| {
-						let body = js_sys::ArrayBuffer::from(body);
-						let body =
-							js_sys::DataView::new(&body, 0, body.byte_length().try_into().unwrap());
+		Ok(request)
+	}
+	// https://datatracker.ietf.org/doc/html/rfc7009#section-2.1
+	async fn revoke_token(&self, client_id: &str) -> Result<(), Error> {
+		let endpoint = match self.capabilities.get_revocation_endpoint() {
+			Some(endpoint) => endpoint,
+			None => return Ok(()),
+		};
 
-						let mut buffer = vec![];
-						for i in 0..body.byte_length() {
-							buffer.push(body.get_uint8(i));
-						}
+		let request = build_form_request(
+			endpoint,
+			&[
+				("token", self.token.get_access_token()),
+				("token_type_hint", "access_token"),
+				("client_id", client_id),
+			],
+		)?;
+
+		let resp = self.send(&request).await?;
+		if !resp.ok() {
+			return Err(Error::from_status(resp.status(), endpoint));
+		}
 
-						resolve
-							.call1(
-								&JsValue::NULL,
-								&JsValue::from_serde(&Document {
-									etag,
-									content: buffer,
-									content_type,
-								})
-								.unwrap(),
-							)
-							.unwrap();
-					}) as Box<dyn FnOnce(JsValue)>);
-
-					let body_err = Closure::wrap(Box::new(move |err: JsValue| {
-						reject_for_main
-							.call1(&JsValue::NULL, &format!("{:?}", err).into())
-							.unwrap();
-					}) as Box<dyn FnMut(JsValue)>);
-
-					resp.array_buffer()
-						.unwrap()
-						.then(&body_process)
-						.catch(&body_err);
-
-					body_process.forget();
-					body_err.forget();
-				} else if resp.status() == 404 {
-					reject_for_main
-						.call1(
-							&JsValue::NULL,
-							&JsValue::from_str("document does not exists yet in database"),
-						)
-						.unwrap();
-				} else {
-					reject_for_main
-						.call1(
-							&JsValue::NULL,
-							&JsValue::from_str(&format!(
-								"error {} when access to database",
-								resp.status()
-							)),
-						)
-						.unwrap();
-				}
-			}) as Box<dyn FnOnce(JsValue)>);
+		Ok(())
+	}
+	// https://datatracker.ietf.org/doc/html/rfc6749#section-6
+	async fn refresh(&self, client_id: &str) -> Result<Client, Error> {
+		let refresh_token = self.token.get_refresh_token().ok_or(Error::Unauthorized)?;
+		let endpoint = self
+			.capabilities
+			.get_token_endpoint()
+			.ok_or(Error::Unsupported(webfinger::TOKEN_ENDPOINT_KEY))?;
+
+		let response = request_token(
+			endpoint,
+			&[
+				("grant_type", "refresh_token"),
+				("refresh_token", refresh_token),
+				("client_id", client_id),
+			],
+			&self.settings,
+		)
+		.await?;
+
+		let mut token = token_store::StoredToken::from_response(&response)
+			.with_storage_link(self.token.get_storage_link().cloned());
+		if token.get_refresh_token().is_none() {
+			// the server may keep the same refresh token
+			token = token.with_refresh_token(Some(String::from(refresh_token)));
+		}
 
-			let err_callback = Closure::wrap(Box::new(move |err: JsValue| {
-				reject
-					.call1(&JsValue::NULL, &format!("{:?}", err).into())
-					.unwrap();
-			}) as Box<dyn FnMut(JsValue)>);
+		Ok(Client {
+			token,
+			..self.clone()
+		})
+	}
+	pub async fn get_document(
+		&self,
+		path: impl Into<String>,
+		if_none_match: Option<String>,
+	) -> Result<DocumentResponse, Error> {
+		let path = path.into();
 
-			window
-				.fetch_with_request(&request)
-				.then(&process_callback)
-				.catch(&err_callback);
+		let request = self.build_request("GET", &path, None)?;
+		if let Some(if_none_match) = if_none_match {
+			request.headers().set("If-None-Match", &if_none_match)?;
+		}
 
-			process_callback.forget();
-			err_callback.forget();
-		}))
+		let resp = self.send(&request).await?;
+
+		if resp.status() == 304 {
+			return Ok(DocumentResponse::NotModified);
+		} else if !resp.ok() {
+			return Err(Error::from_status(resp.status(), &path));
+		}
+
+		Ok(DocumentResponse::Content(read_document(resp).await?))
 	}
-	pub fn put_document(
+	pub async fn get_document_range(
 		&self,
 		path: impl Into<String>,
-		document: &Document,
-	) -> Result<Promise, JsValue> {
+		range: std::ops::Range<u64>,
+	) -> Result<Document, Error> {
 		let path = path.into();
 
-		let mut opts = web_sys::RequestInit::new();
-		opts.method("PUT");
-		opts.body(Some(&js_sys::Uint8Array::from(document.content.as_slice())));
-		opts.mode(web_sys::RequestMode::Cors);
-
-		let full_path = format!("{}{}", self.server_path, path);
+		if !self.capabilities.supports_range_requests() {
+			return Err(Error::Unsupported(webfinger::RANGE_KEY));
+		}
+		if range.is_empty() {
+			return Err(Error::InvalidPath {
+				path,
+				reason: "requested range is empty",
+			});
+		}
 
-		let request = web_sys::Request::new_with_str_and_init(&full_path, &opts).unwrap();
+		let request = self.build_request("GET", &path, None)?;
 		request
 			.headers()
-			.set("Authorization", &format!("Bearer {}", self.access_token))
-			.unwrap();
+			.set("Range", &format!("bytes={}-{}", range.start, range.end - 1))?;
+
+		let resp = self.send(&request).await?;
+
+		if !resp.ok() {
+			return Err(Error::from_status(resp.status(), &path));
+		}
+
+		read_document(resp).await
+	}
+	pub fn document_url(&self, path: impl Into<String>) -> Result<String, Error> {
+		if !self.capabilities.supports_query_token() {
+			return Err(Error::Unsupported(webfinger::QUERY_TOKEN_KEY));
+		}
+
+		Ok(format!(
+			"{}{}?access_token={}",
+			self.server_path,
+			path.into(),
+			pct_str::PctString::encode(self.token.get_access_token().chars(), pct_str::URIReserved)
+		))
+	}
+	pub fn web_authoring_url(&self, path: impl Into<String>) -> Result<String, Error> {
+		let path = path.into();
+
+		let domain = self
+			.capabilities
+			.get_web_authoring_domain()
+			.ok_or(Error::Unsupported(webfinger::WEB_AUTHORING_KEY))?;
+
+		match path.strip_prefix(WEB_AUTHORING_FOLDER) {
+			Some(page) => Ok(format!("https://{}/{}", domain, page)),
+			None => Err(Error::InvalidPath {
+				path,
+				reason: "only documents in `/public/www/` are published by web authoring",
+			}),
+		}
+	}
+	pub async fn put_document(
+		&self,
+		path: impl Into<String>,
+		document: &Document,
+		precondition: &Precondition,
+	) -> Result<Option<String>, Error> {
+		let path = path.into();
+
+		let body = js_sys::Uint8Array::from(document.content.as_slice());
+		let request = self.build_request("PUT", &path, Some(&body))?;
 		request
 			.headers()
-			.set("Content-Type", &document.content_type)
-			.unwrap();
+			.set("Content-Type", &document.content_type)?;
+		match precondition {
+			Precondition::Unconditional => {}
+			Precondition::IfMatch(etag) => request.headers().set("If-Match", etag)?,
+			Precondition::CreateOnly => request.headers().set("If-None-Match", "*")?,
+		}
+
+		let resp = self.send(&request).await?;
+
+		if resp.ok() {
+			Ok(resp.headers().get("etag")?)
+		} else {
+			Err(Error::from_status(resp.status(), &path))
+		}
+	}
+	pub async fn delete_document(
+		&self,
+		path: impl Into<String>,
+		if_match: Option<String>,
+	) -> Result<bool, Error> {
+		let path = path.into();
+
+		let request = self.build_request("DELETE", &path, None)?;
+		if let Some(if_match) = if_match {
+			request.headers().set("If-Match", &if_match)?;
+		}
+
+		let resp = self.send(&request).await?;
+
+		if resp.ok() {
+			Ok(true)
+		} else if resp.status() == 404 {
+			Ok(false)
+		} else {
+			Err(Error::from_status(resp.status(), &path))
+		}
+	}
+	pub async fn list_folder(&self, path: impl Into<String>) -> Result<Folder, Error> {
+		let path = path.into();
+
+		if !path.ends_with('/') {
+			return Err(Error::InvalidPath {
+				path,
+				reason: "folder path must end with `/`",
+			});
+		}
+
+		let request = self.build_request("GET", &path, None)?;
+
+		let resp = self.send(&request).await?;
+
+		if resp.status() == 404 {
+			// older servers answer 404 for folders without any document in it
+			return Ok(Folder::default());
+		} else if !resp.ok() {
+			return Err(Error::from_status(resp.status(), &path));
+		}
+
+		let etag = resp.headers().get("etag")?;
+
+		let body = wasm_bindgen_futures::JsFuture::from(resp.text()?)
+			.await
+			.map_err(|err| Error::Network(js_error_message(&err)))?;
+
+		Folder::parse(etag, &body.as_string().unwrap_or_default())
+	}
+}
+
+fn is_online() -> bool {
+	web_sys::window()
+		.map(|window| window.navigator().on_line())
+		.unwrap_or(true)
+}
+
+fn session_storage() -> Result<web_sys::Storage, Error> {
+	utils::window()?
+		.session_storage()?
+		.ok_or_else(|| Error::Js(String::from("sessionStorage not found")))
+}
+
+fn build_form_request(endpoint: &str, params: &[(&str, &str)]) -> Result<web_sys::Request, Error> {
+	let body = params
+		.iter()
+		.map(|(name, value)| {
+			format!(
+				"{}={}",
+				name,
+				pct_str::PctString::encode(value.chars(), pct_str::URIReserved)
+			)
+		})
+		.collect::<Vec<String>>()
+		.join("&");
+
+	let mut opts = web_sys::RequestInit::new();
+	opts.method("POST");
+	opts.body(Some(&JsValue::from_str(&body)));
+	opts.mode(web_sys::RequestMode::Cors);
+
+	let request = web_sys::Request::new_with_str_and_init(endpoint, &opts)?;
+	request
+		.headers()
+		.set("Content-Type", "application/x-www-form-urlencoded")?;
+
+	Ok(request)
+}
+
+// https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.3
+async fn request_token(
+	endpoint: &str,
+	params: &[(&str, &str)],
+	settings: &RequestSettings,
+) -> Result<oauth::TokenResponse, Error> {
+	let request = build_form_request(endpoint, params)?;
+	request.headers().set("Accept", "application/json")?;
+
+	let resp = fetch(&request, settings).await?;
+	let body = wasm_bindgen_futures::JsFuture::from(resp.text()?)
+		.await?
+		.as_string()
+		.unwrap_or_default();
+
+	if !resp.ok() {
+		return Err(match serde_json::from_str::<oauth::AuthError>(&body) {
+			Ok(err) => Error::OAuth(err),
+			Err(_) => Error::from_status(resp.status(), endpoint),
+		});
+	}
+
+	serde_json::from_str(&body).map_err(|err| Error::MalformedResponse(format!("{}", err)))
+}
+
+fn generate_cookie_name_header(
+	client_id: &str,
+	user_address: &webfinger::UserAddress,
+	scopes: &[scope::Scope],
+) -> Result<String, Error> {
+	let client_id_uri_obj = client_id
+		.parse::<http::uri::Uri>()
+		.ok()
+		.filter(|uri| uri.host().is_some_and(|host| !host.is_empty()))
+		.ok_or_else(|| Error::InvalidClientId {
+			client_id: String::from(client_id),
+			reason: "the client id must be an absolute URL, as the origin of the application",
+		})?;
+	let host = client_id_uri_obj.host().unwrap_or_default();
+
+	let cookie_name_header = format!(
+		"{}|{}|{}|{}|",
+		match client_id_uri_obj.port() {
+			Some(port) => format!("{}:{}", host, port),
+			None => String::from(host),
+		},
+		user_address.get_user(),
+		user_address.get_host(),
+		scope::Scope::serialize_list(scopes)
+	);
+	let cookie_name_header =
+		pct_str::PctString::encode(cookie_name_header.chars(), pct_str::URIReserved);
+
+	Ok(cookie_name_header.to_string())
+}
+
+async fn read_document(resp: web_sys::Response) -> Result<Document, Error> {
+	let headers = resp.headers();
+	let etag = headers.get("etag")?;
+	let last_modified = headers
+		.get("last-modified")?
+		.map(|date| js_sys::Date::parse(&date))
+		.filter(|date| !date.is_nan());
+	let content_type = headers.get("content-type")?.ok_or_else(|| {
+		Error::MalformedResponse(String::from(
+			"missing `Content-Type` header from server response",
+		))
+	})?;
+
+	let body = wasm_bindgen_futures::JsFuture::from(resp.array_buffer()?)
+		.await
+		.map_err(|err| Error::Network(js_error_message(&err)))?;
+
+	Ok(Document {
+		etag,
+		content: js_sys::Uint8Array::new(&body).to_vec(),
+		content_type,
+		last_modified,
+	})
+}
+
+async fn fetch(
+	request: &web_sys::Request,
+	settings: &RequestSettings,
+) -> Result<web_sys::Response, Error> {
+	let window = utils::window()?;
+
+	let (request, timer) = match settings.timeout {
+		Some(timeout) => {
+			let controller = web_sys::AbortController::new()?;
+
+			let mut opts = web_sys::RequestInit::new();
+			opts.signal(Some(&controller.signal()));
+			let request = web_sys::Request::new_with_request_and_init(request, &opts)?;
 
-		let window = web_sys::window().ok_or("window not found")?;
+			let abort = Closure::once(move || controller.abort());
+			let handle = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+				abort.as_ref().unchecked_ref(),
+				timeout.as_millis().try_into().unwrap_or(i32::MAX),
+			)?;
 
-		Ok(window.fetch_with_request(&request))
+			(request, Some((handle, abort)))
+		}
+		None => (Clone::clone(request), None),
+	};
+
+	let resp = wasm_bindgen_futures::JsFuture::from(window.fetch_with_request(&request)).await;
+
+	if let Some((handle, abort)) = timer {
+		window.clear_timeout_with_handle(handle);
+		drop(abort);
+	}
+
+	let resp = resp.map_err(|err| {
+		let aborted = err
+			.dyn_ref::<web_sys::DomException>()
+			.map(|err| err.name() == "AbortError")
+			.unwrap_or(false);
+
+		match settings.timeout {
+			Some(timeout) if aborted => Error::Timeout(timeout),
+			_ => Error::Network(js_error_message(&err)),
+		}
+	});
+
+	if settings.logging {
+		let status = match &resp {
+			Ok(resp) => resp
+				.dyn_ref::<web_sys::Response>()
+				.map(|resp| resp.status().to_string())
+				.unwrap_or_default(),
+			Err(err) => err.to_string(),
+		};
+		web_sys::console::debug_1(
+			&format!("{} {} -> {}", request.method(), request.url(), status).into(),
+		);
 	}
+
+	Ok(resp?.dyn_into()?)
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum DocumentResponse {
+	Content(Document),
+	NotModified,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Document {
-	etag: Option<String>, // TODO
+	etag: Option<String>,
 	content: Vec<u8>,
 	content_type: String,
+	/// `Last-Modified` header, in milliseconds since epoch
+	#[serde(default)]
+	last_modified: Option<f64>,
 }
 impl From<isize> for Document {
 	fn from(input: isize) -> Self {
@@ -588,21 +2484,215 @@ impl From<isize> for Document {
 			etag: None,
 			content: input.to_be_bytes().to_vec(),
 			content_type: String::from("text/plain"),
+			last_modified: None,
 		}
 	}
 }
 impl Document {
+	pub fn with_etag(mut self, etag: Option<String>) -> Self {
+		self.etag = etag;
+		self
+	}
+	pub fn get_etag(&self) -> Option<&str> {
+		self.etag.as_deref()
+	}
 	pub fn get_content(&self) -> &[u8] {
 		self.content.as_slice()
 	}
+	pub fn get_content_type(&self) -> &str {
+		&self.content_type
+	}
+	pub fn get_last_modified(&self) -> Option<f64> {
+		self.last_modified
+	}
+}
+
+/// Condition on the version stored on the server for a write to be applied.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum Precondition {
+	/// Applied whatever the stored version.
+	#[default]
+	Unconditional,
+	/// Only applied to the version with this `ETag` (`If-Match`).
+	IfMatch(String),
+	/// Only applied if the document does not exist yet (`If-None-Match: *`).
+	CreateOnly,
+}
+impl Precondition {
+	/// Only applied to the version with `etag`, or to a missing document.
+	pub fn from_etag(etag: Option<&str>) -> Self {
+		match etag {
+			Some(etag) => Self::IfMatch(String::from(etag)),
+			None => Self::CreateOnly,
+		}
+	}
+	pub fn get_etag(&self) -> Option<&str> {
+		match self {
+			Self::IfMatch(etag) => Some(etag),
+			Self::Unconditional | Self::CreateOnly => None,
+		}
+	}
+}
+impl From<Option<String>> for Precondition {
+	fn from(if_match: Option<String>) -> Self {
+		match if_match {
+			Some(etag) => Self::IfMatch(etag),
+			None => Self::Unconditional,
+		}
+	}
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Folder {
+	etag: Option<String>,
+	documents: Vec<FolderDocument>,
+	folders: Vec<FolderFolder>,
+}
+impl Folder {
+	/// Reads the listing of a folder, as answered by current and legacy servers.
+	pub fn parse(etag: Option<String>, body: &str) -> Result<Self, Error> {
+		let description: FolderDescription = serde_json::from_str(body).map_err(|err| {
+			Error::MalformedResponse(format!(
+				"malformed folder description from server : {}",
+				err
+			))
+		})?;
+
+		Self::from_description(etag, description)
+	}
+	fn from_description(
+		etag: Option<String>,
+		description: FolderDescription,
+	) -> Result<Self, Error> {
+		let mut result = Self {
+			etag,
+			documents: vec![],
+			folders: vec![],
+		};
+
+		let items = match description {
+			FolderDescription::Current { items, .. } => items,
+			FolderDescription::Legacy(versions) => {
+				// a current listing without its `items` would be read as legacy
+				if versions.keys().any(|name| name.starts_with('@')) {
+					return Err(Error::MalformedResponse(String::from(
+						"folder description without `items`",
+					)));
+				}
+
+				versions
+					.into_iter()
+					.map(|(name, version)| {
+						let item = FolderDescriptionItem {
+							etag: version.to_string(),
+							content_type: None,
+							content_length: None,
+						};
+
+						(name, item)
+					})
+					.collect()
+			}
+		};
+
+		for (name, item) in items {
+			match name.strip_suffix('/') {
+				Some(name) => result.folders.push(FolderFolder {
+					name: String::from(name),
+					etag: item.etag,
+				}),
+				None => result.documents.push(FolderDocument {
+					name,
+					etag: item.etag,
+					content_type: item.content_type,
+					content_length: item.content_length,
+				}),
+			}
+		}
+
+		result.documents.sort_by(|a, b| a.name.cmp(&b.name));
+		result.folders.sort_by(|a, b| a.name.cmp(&b.name));
+
+		Ok(result)
+	}
+	pub fn get_etag(&self) -> Option<&str> {
+		self.etag.as_deref()
+	}
+	pub fn get_documents(&self) -> &[FolderDocument] {
+		self.documents.as_slice()
+	}
+	pub fn get_folders(&self) -> &[FolderFolder] {
+		self.folders.as_slice()
+	}
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct FolderDocument {
+	name: String,
+	etag: String,
+	content_type: Option<String>,
+	content_length: Option<u64>,
+}
+impl FolderDocument {
+	pub fn get_name(&self) -> &str {
+		&self.name
+	}
+	pub fn get_etag(&self) -> &str {
+		&self.etag
+	}
+	pub fn get_content_type(&self) -> Option<&str> {
+		self.content_type.as_deref()
+	}
+	pub fn get_content_length(&self) -> Option<u64> {
+		self.content_length
+	}
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct WebfingerResponse {
-	links: Vec<Link>,
+pub struct FolderFolder {
+	name: String,
+	etag: String,
+}
+impl FolderFolder {
+	pub fn get_name(&self) -> &str {
+		&self.name
+	}
+	pub fn get_etag(&self) -> &str {
+		&self.etag
+	}
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum FolderDescription {
+	Current {
+		#[serde(rename = "@context")]
+		_context: Option<String>,
+		items: std::collections::HashMap<String, FolderDescriptionItem>,
+	},
+	/// draft-00 and draft-01 : only the version of each item
+	Legacy(std::collections::HashMap<String, LegacyVersion>),
+}
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum LegacyVersion {
+	Timestamp(u64),
+	Text(String),
+}
+impl std::fmt::Display for LegacyVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Timestamp(timestamp) => write!(f, "{}", timestamp),
+			Self::Text(text) => f.write_str(text),
+		}
+	}
 }
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-struct Link {
-	href: String,
-	properties: std::collections::HashMap<String, Option<String>>,
+#[derive(Debug, serde::Deserialize)]
+struct FolderDescriptionItem {
+	#[serde(rename = "ETag")]
+	etag: String,
+	#[serde(rename = "Content-Type")]
+	content_type: Option<String>,
+	#[serde(rename = "Content-Length")]
+	content_length: Option<u64>,
 }
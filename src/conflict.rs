@@ -0,0 +1,137 @@
+use crate::client::Document;
+
+/// The version a local write was made on.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Base {
+	/// The write was only creating the document.
+	Missing,
+	Known(Document),
+	/// Not in the cache anymore.
+	#[default]
+	Unknown,
+}
+
+/// A write rejected by the server because the document has been changed
+/// (or removed) since the version the write was made on.
+#[derive(Debug)]
+pub struct Conflict {
+	path: String,
+	base: Base,
+	local: Option<Document>,
+	remote: Option<Document>,
+	local_modified_at: f64,
+}
+impl Conflict {
+	pub fn new(
+		path: impl Into<String>,
+		base: Base,
+		local: Option<Document>,
+		remote: Option<Document>,
+		local_modified_at: f64,
+	) -> Self {
+		Self {
+			path: path.into(),
+			base,
+			local,
+			remote,
+			local_modified_at,
+		}
+	}
+	pub fn get_path(&self) -> &str {
+		&self.path
+	}
+	/// The version the local write was made on, `None` if the document did
+	/// not exist or if the version is not known (see `is_base_known`).
+	pub fn get_base(&self) -> Option<&Document> {
+		match &self.base {
+			Base::Known(base) => Some(base),
+			Base::Missing | Base::Unknown => None,
+		}
+	}
+	/// `false` if the version the local write was made on is not in the cache
+	/// anymore.
+	pub fn is_base_known(&self) -> bool {
+		self.base != Base::Unknown
+	}
+	/// `None` for a deletion.
+	pub fn get_local(&self) -> Option<&Document> {
+		self.local.as_ref()
+	}
+	/// `None` if the document has been removed from the server.
+	pub fn get_remote(&self) -> Option<&Document> {
+		self.remote.as_ref()
+	}
+	/// `Date.now()` when the local write has been made.
+	pub fn get_local_modified_at(&self) -> f64 {
+		self.local_modified_at
+	}
+	pub(crate) fn into_remote(self) -> Option<Document> {
+		self.remote
+	}
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+	/// Drops the local write.
+	Remote,
+	/// Overwrites the server version.
+	Local,
+	/// Writes an other version, `None` to remove the document.
+	Merged(Option<Document>),
+}
+
+pub trait ConflictResolver {
+	fn resolve(&self, conflict: &Conflict) -> Resolution;
+}
+
+#[derive(Debug, Default)]
+pub struct RemoteWins;
+impl ConflictResolver for RemoteWins {
+	fn resolve(&self, _: &Conflict) -> Resolution {
+		Resolution::Remote
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct LocalWins;
+impl ConflictResolver for LocalWins {
+	fn resolve(&self, _: &Conflict) -> Resolution {
+		Resolution::Local
+	}
+}
+
+/// Keeps the most recent version, comparing the `Last-Modified` header of
+/// the server version to the time of the local write. The local version wins
+/// if the server does not tell when its version was modified.
+#[derive(Debug, Default)]
+pub struct LastModifiedWins;
+impl ConflictResolver for LastModifiedWins {
+	fn resolve(&self, conflict: &Conflict) -> Resolution {
+		let remote_modified_at = conflict
+			.get_remote()
+			.and_then(|remote| remote.get_last_modified());
+
+		match remote_modified_at {
+			Some(remote_modified_at) if remote_modified_at > conflict.get_local_modified_at() => {
+				Resolution::Remote
+			}
+			_ => Resolution::Local,
+		}
+	}
+}
+
+/// Writes the version built by a closure from the conflict, `None` removing
+/// the document.
+pub struct Merge<F> {
+	merge: F,
+}
+impl<F: Fn(&Conflict) -> Option<Document>> Merge<F> {
+	pub fn new(merge: F) -> Self {
+		Self { merge }
+	}
+}
+impl<F: Fn(&Conflict) -> Option<Document>> ConflictResolver for Merge<F> {
+	fn resolve(&self, conflict: &Conflict) -> Resolution {
+		Resolution::Merged((self.merge)(conflict))
+	}
+}
@@ -0,0 +1,174 @@
+use crate::client::Error;
+
+/// Texts displayed by the `ConnectWidget`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+	ConnectPrompt,
+	ConnectButton,
+	AddressLabel,
+	AddressPlaceholder,
+	SubmitButton,
+	// the link to the server is displayed between `*Before` and `*After`
+	RedirectBefore,
+	RedirectAfter,
+	PopupBefore,
+	PopupAfter,
+	AbortButton,
+	NextButton,
+	/// `{}` is replaced by the user address.
+	ConnectedAs,
+	DisconnectButton,
+	Syncing,
+	RetryButton,
+	SessionExpired,
+	InvalidAddress,
+	/// `{}` is replaced by the only address the app can connect.
+	OtherAddress,
+	/// The app has been configured with an invalid client id.
+	InvalidClientId,
+	/// No remoteStorage server has been found for the address.
+	StorageNotFound,
+	AccessDenied,
+	NetworkError,
+	/// `{}` is replaced by the description of the error, in English.
+	UnexpectedError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+	#[default]
+	En,
+	Fr,
+}
+impl Locale {
+	/// Picks the bundle of a BCP 47 language tag, as `fr-CA`, falling back to
+	/// English.
+	pub fn from_tag(tag: &str) -> Self {
+		let language = tag.split(['-', '_']).next().unwrap_or_default();
+
+		if language.eq_ignore_ascii_case("fr") {
+			Self::Fr
+		} else {
+			Self::En
+		}
+	}
+	pub fn from_navigator() -> Self {
+		web_sys::window()
+			.and_then(|window| window.navigator().language())
+			.map(|tag| Self::from_tag(&tag))
+			.unwrap_or_default()
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+	locale: Locale,
+	overrides: std::collections::HashMap<MessageKey, String>,
+}
+impl Catalog {
+	pub fn new(locale: Locale) -> Self {
+		Self {
+			locale,
+			overrides: std::collections::HashMap::new(),
+		}
+	}
+	pub fn from_navigator() -> Self {
+		Self::new(Locale::from_navigator())
+	}
+	/// Replaces the text of `key`, whatever the locale.
+	pub fn with_override(mut self, key: MessageKey, text: impl Into<String>) -> Self {
+		self.overrides.insert(key, text.into());
+		self
+	}
+	pub fn get_locale(&self) -> Locale {
+		self.locale
+	}
+	pub fn get(&self, key: MessageKey) -> &str {
+		match self.overrides.get(&key) {
+			Some(text) => text,
+			None => match self.locale {
+				Locale::En => english(key),
+				Locale::Fr => french(key),
+			},
+		}
+	}
+	/// Replaces each `{}` of the text of `key` by the next of `args`.
+	pub fn format(&self, key: MessageKey, args: &[&str]) -> String {
+		let mut result = String::from(self.get(key));
+		for arg in args {
+			result = result.replacen("{}", arg, 1);
+		}
+
+		result
+	}
+	/// The text displayed for `err`.
+	pub fn error(&self, err: &Error) -> String {
+		let key = match err {
+			Error::InvalidUserAddress { .. } => MessageKey::InvalidAddress,
+			Error::InvalidClientId { .. } => MessageKey::InvalidClientId,
+			Error::Webfinger(_) => MessageKey::StorageNotFound,
+			Error::OAuth(err) if err.is_access_denied() => MessageKey::AccessDenied,
+			Error::Unauthorized | Error::Forbidden => MessageKey::AccessDenied,
+			Error::Network(_) | Error::Timeout(_) => MessageKey::NetworkError,
+			err => return self.format(MessageKey::UnexpectedError, &[&err.to_string()]),
+		};
+
+		String::from(self.get(key))
+	}
+}
+
+fn english(key: MessageKey) -> &'static str {
+	match key {
+		MessageKey::ConnectPrompt => "Connect your remoteStorage",
+		MessageKey::ConnectButton => "Connect",
+		MessageKey::AddressLabel => "Your storage address",
+		MessageKey::AddressPlaceholder => "user@example.com",
+		MessageKey::SubmitButton => "Connect",
+		MessageKey::RedirectBefore => "You will be temporarily redirected to ",
+		MessageKey::RedirectAfter => " in order to authenticate on the requested remoteStorage server, then brought back to this page.",
+		MessageKey::PopupBefore => "A new window will be opened on ",
+		MessageKey::PopupAfter => " in order to authenticate on the requested remoteStorage server, without leaving this page.",
+		MessageKey::AbortButton => "Abort",
+		MessageKey::NextButton => "Next >",
+		MessageKey::ConnectedAs => "Connected as {}",
+		MessageKey::DisconnectButton => "Disconnect",
+		MessageKey::Syncing => "Synchronizing…",
+		MessageKey::RetryButton => "Retry",
+		MessageKey::SessionExpired => "The session has expired, please connect again.",
+		MessageKey::InvalidAddress => "The address must be written as user@example.com.",
+		MessageKey::OtherAddress => "Only {} can be connected here.",
+		MessageKey::InvalidClientId => "This application is misconfigured and can not be connected.",
+		MessageKey::StorageNotFound => "No remoteStorage server has been found for this address.",
+		MessageKey::AccessDenied => "The access to the storage has been denied.",
+		MessageKey::NetworkError => "The server can not be reached, please check your connection.",
+		MessageKey::UnexpectedError => "An error occurred : {}",
+	}
+}
+
+fn french(key: MessageKey) -> &'static str {
+	match key {
+		MessageKey::ConnectPrompt => "Connectez votre remoteStorage",
+		MessageKey::ConnectButton => "Se connecter",
+		MessageKey::AddressLabel => "L'adresse de votre stockage",
+		MessageKey::AddressPlaceholder => "utilisateur@exemple.fr",
+		MessageKey::SubmitButton => "Se connecter",
+		MessageKey::RedirectBefore => "Vous allez être temporairement redirigé vers ",
+		MessageKey::RedirectAfter => " afin de vous authentifier sur le serveur remoteStorage demandé, puis ramené sur cette page.",
+		MessageKey::PopupBefore => "Une nouvelle fenêtre va s'ouvrir sur ",
+		MessageKey::PopupAfter => " afin de vous authentifier sur le serveur remoteStorage demandé, sans quitter cette page.",
+		MessageKey::AbortButton => "Annuler",
+		MessageKey::NextButton => "Suivant >",
+		MessageKey::ConnectedAs => "Connecté en tant que {}",
+		MessageKey::DisconnectButton => "Se déconnecter",
+		MessageKey::Syncing => "Synchronisation…",
+		MessageKey::RetryButton => "Réessayer",
+		MessageKey::SessionExpired => "La session a expiré, veuillez vous reconnecter.",
+		MessageKey::InvalidAddress => "L'adresse doit être de la forme utilisateur@exemple.fr.",
+		MessageKey::OtherAddress => "Seul {} peut être connecté ici.",
+		MessageKey::InvalidClientId => "Cette application est mal configurée et ne peut pas être connectée.",
+		MessageKey::StorageNotFound => "Aucun serveur remoteStorage n'a été trouvé pour cette adresse.",
+		MessageKey::AccessDenied => "L'accès au stockage a été refusé.",
+		MessageKey::NetworkError => "Le serveur est injoignable, veuillez vérifier votre connexion.",
+		MessageKey::UnexpectedError => "Une erreur est survenue : {}",
+	}
+}
@@ -0,0 +1,150 @@
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::{client::Error, utils};
+
+// IndexedDB only reports its results with `onsuccess` / `onerror` callbacks,
+// so they are plugged into a `Promise` to be awaited.
+async fn request_result(request: &web_sys::IdbRequest) -> Result<JsValue, Error> {
+	let promise = js_sys::Promise::new(&mut |resolve, reject| {
+		request.set_onsuccess(Some(&resolve));
+		request.set_onerror(Some(&reject));
+	});
+
+	let outcome = wasm_bindgen_futures::JsFuture::from(promise).await;
+
+	request.set_onsuccess(None);
+	request.set_onerror(None);
+
+	match outcome {
+		Ok(_) => Ok(request.result()?),
+		Err(_) => Err(Error::Js(
+			request
+				.error()
+				.ok()
+				.flatten()
+				.map(|err| err.message())
+				.unwrap_or_else(|| String::from("IndexedDB request failed")),
+		)),
+	}
+}
+
+pub(crate) async fn open(
+	name: &str,
+	version: u32,
+	stores: &'static [&'static str],
+) -> Result<web_sys::IdbDatabase, Error> {
+	let factory = utils::window()?
+		.indexed_db()?
+		.ok_or(Error::Unsupported("IndexedDB"))?;
+
+	let request = factory.open_with_u32(name, version)?;
+
+	let upgrade_request = request.clone();
+	let on_upgrade_needed = Closure::wrap(Box::new(move || {
+		if let Ok(db) = upgrade_request
+			.result()
+			.and_then(|db| db.dyn_into::<web_sys::IdbDatabase>())
+		{
+			let existing = db.object_store_names();
+			for store in stores {
+				if !existing.contains(store) {
+					db.create_object_store(store).ok();
+				}
+			}
+		}
+	}) as Box<dyn FnMut()>);
+	request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+
+	let db = request_result(&request).await;
+
+	request.set_onupgradeneeded(None);
+	drop(on_upgrade_needed);
+
+	Ok(db?.unchecked_into())
+}
+
+fn object_store(
+	db: &web_sys::IdbDatabase,
+	store: &str,
+	mode: web_sys::IdbTransactionMode,
+) -> Result<web_sys::IdbObjectStore, Error> {
+	Ok(db
+		.transaction_with_str_and_mode(store, mode)?
+		.object_store(store)?)
+}
+
+pub(crate) async fn get(
+	db: &web_sys::IdbDatabase,
+	store: &str,
+	key: &str,
+) -> Result<Option<JsValue>, Error> {
+	let request = object_store(db, store, web_sys::IdbTransactionMode::Readonly)?
+		.get(&JsValue::from_str(key))?;
+
+	let value = request_result(&request).await?;
+
+	Ok(if value.is_undefined() {
+		None
+	} else {
+		Some(value)
+	})
+}
+
+pub(crate) async fn put(
+	db: &web_sys::IdbDatabase,
+	store: &str,
+	key: &str,
+	value: &JsValue,
+) -> Result<(), Error> {
+	let request = object_store(db, store, web_sys::IdbTransactionMode::Readwrite)?
+		.put_with_key(value, &JsValue::from_str(key))?;
+
+	request_result(&request).await?;
+
+	Ok(())
+}
+
+pub(crate) async fn delete(db: &web_sys::IdbDatabase, store: &str, key: &str) -> Result<(), Error> {
+	let request = object_store(db, store, web_sys::IdbTransactionMode::Readwrite)?
+		.delete(&JsValue::from_str(key))?;
+
+	request_result(&request).await?;
+
+	Ok(())
+}
+
+fn prefix_range(prefix: &str) -> Result<JsValue, Error> {
+	Ok(web_sys::IdbKeyRange::bound(
+		&JsValue::from_str(prefix),
+		&JsValue::from_str(&format!("{}\u{ffff}", prefix)),
+	)?
+	.into())
+}
+
+/// Values whose key starts with `prefix`, sorted by key.
+pub(crate) async fn get_all(
+	db: &web_sys::IdbDatabase,
+	store: &str,
+	prefix: &str,
+) -> Result<Vec<JsValue>, Error> {
+	let request = object_store(db, store, web_sys::IdbTransactionMode::Readonly)?
+		.get_all_with_key(&prefix_range(prefix)?)?;
+
+	let values = request_result(&request).await?;
+
+	Ok(js_sys::Array::from(&values).to_vec())
+}
+
+/// Removes the values whose key starts with `prefix`.
+pub(crate) async fn delete_all(
+	db: &web_sys::IdbDatabase,
+	store: &str,
+	prefix: &str,
+) -> Result<(), Error> {
+	let request = object_store(db, store, web_sys::IdbTransactionMode::Readwrite)?
+		.delete(&prefix_range(prefix)?)?;
+
+	request_result(&request).await?;
+
+	Ok(())
+}
@@ -1,8 +1,18 @@
+mod idb;
 mod utils;
 
-mod client;
-
-use serde::{Deserialize, Serialize};
+pub mod cache;
+pub mod change;
+pub mod client;
+pub mod conflict;
+pub mod i18n;
+pub mod oauth;
+pub mod outbox;
+pub mod scope;
+pub mod sync;
+pub mod token_store;
+pub mod webfinger;
+pub mod widget;
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -13,13 +23,15 @@ use wasm_bindgen::JsCast;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-const COUNTER_PATH: &str = "/experimental_counter/counter";
+const COUNTERS_FOLDER: &str = "/experimental_counter/";
+const DEFAULT_COUNTER: &str = "counter";
+const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 
 #[wasm_bindgen]
 pub async fn run(
-	webfinger_uri: String,
-	user: String,
-	scope: String, // TODO : make it multiple
+	user_address: String,
+	webfinger_root: Option<String>,
+	scopes: String,
 	client_id: Option<String>,
 ) -> Result<(), JsValue> {
 	utils::set_panic_hook();
@@ -32,35 +44,172 @@ pub async fn run(
 		.dyn_ref::<web_sys::HtmlDocument>()
 		.ok_or("can not cast document as HtmlDocument")?;
 
-	let remote = client::ClientRemote::new(
-		webfinger_uri,
-		user,
-		scope,
-		client_id.unwrap_or(window.location().origin()?),
-		true,
-	)
-	.await?;
-
-	if !remote.is_connected() {
-		remote.show_connect_overlay().await?;
+	let mut builder = client::ClientRemote::builder()
+		.user_address(user_address.parse()?)
+		.scopes(scope::Scope::parse_list(&scopes)?)
+		.cache(cache::DocumentCache::default())
+		.outbox(outbox::Outbox::default())
+		.conflict_resolver(conflict::Merge::new(merge_counters))
+		.timeout(std::time::Duration::from_secs(10))
+		.logging(true);
+	if let Some(webfinger_root) = webfinger_root {
+		builder = builder.webfinger_root(webfinger_root);
+	}
+	if let Some(client_id) = client_id {
+		builder = builder.client_id(client_id);
+	}
+	let remote = builder.build()?;
+
+	let connected = remote.connect().await;
+
+	let remote = std::rc::Rc::new(remote);
+
+	let body = document.body().ok_or("body not found")?;
+	let widget_container = document.create_element("div")?;
+	widget_container.set_attribute("id", "remotestorage_widget")?;
+	body.append_child(&widget_container)?;
+	let widget = widget::ConnectWidget::mount(&widget_container)?;
+	client::ClientRemote::bind_widget(&remote, &widget)?;
+
+	if let Err(err) = connected {
+		match err {
+			// the user may want to try again, with an other account for example
+			client::Error::OAuth(_) | client::Error::OAuthStateMismatch => {
+				widget.set_state(widget::WidgetState::Error(widget.get_catalog().error(&err)))?;
+			}
+			// the cached values are displayed anyway
+			client::Error::Network(_) | client::Error::Timeout(_) => {
+				web_sys::console::error_1(&err.into());
+			}
+			err => return Err(err.into()),
+		}
+	}
+
+	let mut counters = vec![];
+	if remote.is_connected() {
+		match remote.list_folder(COUNTERS_FOLDER).await {
+			Ok(folder) => {
+				for document in folder.get_documents() {
+					counters.push(String::from(document.get_name()));
+				}
+			}
+			Err(err @ (client::Error::Network(_) | client::Error::Timeout(_))) => {
+				web_sys::console::error_1(&err.into());
+			}
+			Err(err) => return Err(err.into()),
+		}
+	}
+	if counters.is_empty() {
+		counters.push(String::from(DEFAULT_COUNTER));
 	}
 
-	let remote = std::sync::Arc::new(remote);
+	for name in counters {
+		let counter = create_counter(document, &name, remote.clone())?;
+		body.append_child(&counter)?;
+
+		// offline, the last known value may be in the cache
+		if remote.is_connected() {
+			if let Err(err) = update_counter_value(&name, &remote).await {
+				web_sys::console::error_1(&err.into());
+			}
+		}
+	}
+
+	// the writes of this page, of the other tabs and of the other devices
+	remote.on_change(COUNTERS_FOLDER, |change| {
+		let name = &change.get_path()[COUNTERS_FOLDER.len()..];
+		if let Ok(value_display) = find_value_display(name) {
+			match change.get_new_value() {
+				Some(doc) => display_counter_value(
+					&value_display,
+					counter_value(doc),
+					doc.get_etag(),
+					cache::Freshness::Fresh,
+				),
+				None => display_counter_value(&value_display, 0, None, cache::Freshness::Fresh),
+			}
+		}
+	});
+
+	let sync = sync::SyncEngine::new(remote.clone());
+	sync.on_event(|event| {
+		if let sync::SyncEvent::Failed(err) = event {
+			web_sys::console::error_1(&format!("{}", err).into());
+		}
+	});
+	sync.start(SYNC_INTERVAL)?;
+	// the engine stops when dropped, it has to live as long as the page
+	std::mem::forget(sync);
+
+	Ok(())
+}
+
+fn counter_value(document: &client::Document) -> isize {
+	document
+		.get_content()
+		.try_into()
+		.map(isize::from_be_bytes)
+		.unwrap_or_default()
+}
+
+/// Sums the concurrent increments of a counter : the local increment (from
+/// the version it was made on) is applied to the server value. A reset wins.
+/// Without the version it was made on, the local increment is unknown and
+/// dropped.
+pub fn merge_counters(conflict: &conflict::Conflict) -> Option<client::Document> {
+	let local = conflict.get_local()?;
+	if !conflict.is_base_known() {
+		return conflict.get_remote().cloned();
+	}
+
+	let value =
+		|document: Option<&client::Document>| document.map(counter_value).unwrap_or_default();
+
+	let increment = counter_value(local) - value(conflict.get_base());
+
+	Some(client::Document::from(
+		value(conflict.get_remote()) + increment,
+	))
+}
+
+fn counter_path(name: &str) -> String {
+	format!("{}{}", COUNTERS_FOLDER, name)
+}
+
+fn value_display_id(name: &str) -> String {
+	format!("value_display_{}", name)
+}
 
+fn find_value_display(name: &str) -> Result<web_sys::Element, client::Error> {
+	web_sys::window()
+		.and_then(|window| window.document())
+		.and_then(|document| document.get_element_by_id(&value_display_id(name)))
+		.ok_or_else(|| client::Error::Js(format!("can not found #{}", value_display_id(name))))
+}
+
+fn create_counter(
+	document: &web_sys::HtmlDocument,
+	name: &str,
+	remote: std::rc::Rc<client::ClientRemote>,
+) -> Result<web_sys::Element, JsValue> {
 	let buttons = document.create_element("p")?;
-	buttons.set_attribute("id", "buttons")?;
+	buttons.set_attribute("id", &format!("buttons_{}", name))?;
+
+	let label = document.create_element("span")?;
+	label.set_text_content(Some(&format!("{} : ", name)));
+	buttons.append_child(&label)?;
 
 	let value_display = document.create_element("span")?;
-	value_display.set_attribute("id", "value_display")?;
+	value_display.set_attribute("id", &value_display_id(name))?;
 	value_display.set_inner_html(&format!("&nbsp;{}&nbsp;", 0));
 
 	let sub = document.create_element("button")?;
-	sub.set_attribute("id", "sub_button")?;
+	sub.set_attribute("id", &format!("sub_button_{}", name))?;
 	sub.set_inner_html("-");
-	let sub_value = value_trigger(-1, remote.clone())?;
+	let sub_value = value_trigger(-1, name, remote.clone());
 	let sub = sub
 		.dyn_ref::<web_sys::HtmlElement>()
-		.ok_or("can not cast #sub_button as HtmlElement")?;
+		.ok_or("can not cast sub button as HtmlElement")?;
 	sub.set_onclick(Some(sub_value.as_ref().unchecked_ref()));
 	sub_value.forget();
 	buttons.append_child(sub)?;
@@ -68,116 +217,154 @@ pub async fn run(
 	buttons.append_child(&value_display)?;
 
 	let add = document.create_element("button")?;
-	add.set_attribute("id", "add_button")?;
+	add.set_attribute("id", &format!("add_button_{}", name))?;
 	add.set_inner_html("+");
-	let add_value = value_trigger(1, remote.clone())?;
+	let add_value = value_trigger(1, name, remote.clone());
 	let add = add
 		.dyn_ref::<web_sys::HtmlElement>()
-		.ok_or("can not cast #add_button as HtmlElement")?;
+		.ok_or("can not cast add button as HtmlElement")?;
 	add.set_onclick(Some(add_value.as_ref().unchecked_ref()));
 	add_value.forget();
 	buttons.append_child(add)?;
 
-	document
-		.body()
-		.ok_or("body not found")?
-		.append_child(&buttons)?;
-
-	update_counter_value(remote).ok();
-
-	Ok(())
-}
+	let reset = document.create_element("button")?;
+	reset.set_attribute("id", &format!("reset_button_{}", name))?;
+	reset.set_inner_html("reset");
+	let reset_value = reset_trigger(name, remote);
+	let reset = reset
+		.dyn_ref::<web_sys::HtmlElement>()
+		.ok_or("can not cast reset button as HtmlElement")?;
+	reset.set_onclick(Some(reset_value.as_ref().unchecked_ref()));
+	reset_value.forget();
+	buttons.append_child(reset)?;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct WebfingerResponse {
-	links: Vec<Link>,
+	Ok(buttons)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Link {
-	href: String,
-	properties: std::collections::HashMap<String, Option<String>>,
+fn display_counter_value(
+	value_display: &web_sys::Element,
+	value: isize,
+	etag: Option<&str>,
+	freshness: cache::Freshness,
+) {
+	value_display.set_inner_html(&format!("&nbsp;{}&nbsp;", value));
+	match etag {
+		Some(etag) => value_display.set_attribute("data-etag", etag).ok(),
+		None => value_display.remove_attribute("data-etag").ok(),
+	};
+	let stale = freshness == cache::Freshness::Stale;
+	value_display
+		.class_list()
+		.toggle_with_force("stale", stale)
+		.ok();
 }
 
-fn update_counter_value(remote: std::sync::Arc<client::ClientRemote>) -> Result<(), JsValue> {
-	if remote.is_connected() {
-		let window = web_sys::window().ok_or("window not found")?;
-		let document = window.document().ok_or("document not found")?;
-
-		let promise = remote.get_document(COUNTER_PATH, None)?;
-
-		let value_display = document
-			.get_element_by_id("value_display")
-			.ok_or("can not found #value_display")?;
-
-		let process_callback = Closure::once(Box::new(move |resp: JsValue| {
-			let doc = resp.into_serde::<client::Document>().unwrap();
-
-			let body = &[
-				*doc.get_content().get(0).unwrap(),
-				*doc.get_content().get(1).unwrap(),
-				*doc.get_content().get(2).unwrap(),
-				*doc.get_content().get(3).unwrap(),
-			];
+async fn update_counter_value(
+	name: &str,
+	remote: &client::ClientRemote,
+) -> Result<(), client::Error> {
+	let value_display = find_value_display(name)?;
+
+	match remote.read_document(counter_path(name)).await {
+		Ok(cached) => {
+			let doc = cached.get_document();
+			let value = counter_value(doc);
+
+			display_counter_value(
+				&value_display,
+				value,
+				doc.get_etag(),
+				cached.get_freshness(),
+			);
+
+			Ok(())
+		}
+		Err(client::Error::NotFound { .. }) => {
+			display_counter_value(&value_display, 0, None, cache::Freshness::Fresh);
 
-			let value = isize::from_be_bytes(*body);
+			Ok(())
+		}
+		Err(err) => Err(err),
+	}
+}
 
-			value_display.set_inner_html(&format!("&nbsp;{}&nbsp;", value));
-		}) as Box<dyn FnOnce(JsValue)>);
+async fn increment_counter(
+	increment: i8,
+	name: &str,
+	remote: &client::ClientRemote,
+) -> Result<(), client::Error> {
+	let value_display = find_value_display(name)?;
+
+	let val: String = value_display
+		.text_content()
+		.unwrap_or_else(|| String::from("0"));
+	let val = val.trim().parse::<isize>().unwrap_or_default() + increment as isize;
+
+	// without `ETag`, the document is only created if it does not exists yet
+	let etag = value_display.get_attribute("data-etag");
+	let precondition = client::Precondition::from_etag(etag.as_deref());
+
+	// the display is updated by the change listener
+	if let Err(err) = remote
+		.put_document(
+			counter_path(name),
+			&client::Document::from(val),
+			precondition,
+		)
+		.await
+	{
+		if let client::Error::PreconditionFailed { .. } = err {
+			// an other client changed the counter since our last read
+			update_counter_value(name, remote).await?;
+		}
 
-		let err_callback = Closure::wrap(Box::new(move |err: JsValue| {
-			web_sys::console::error_1(&format!("{:?}", err).into());
-		}) as Box<dyn FnMut(JsValue)>);
+		return Err(err);
+	}
 
-		promise.then(&process_callback).catch(&err_callback);
+	Ok(())
+}
 
-		process_callback.forget();
-		err_callback.forget();
+async fn reset_counter(name: &str, remote: &client::ClientRemote) -> Result<(), client::Error> {
+	remote.delete_document(counter_path(name), None).await?;
 
-		Ok(())
-	} else {
-		Err(JsValue::from_str("database connection not established"))
-	}
+	Ok(())
 }
 
 fn value_trigger(
 	increment: i8,
-	remote: std::sync::Arc<client::ClientRemote>,
-) -> Result<Closure<dyn FnMut()>, JsValue> {
-	let window = web_sys::window().ok_or("window not found")?;
-	let document = window.document().ok_or("document not found")?;
+	name: &str,
+	remote: std::rc::Rc<client::ClientRemote>,
+) -> Closure<dyn FnMut()> {
+	let name = String::from(name);
+
+	Closure::wrap(Box::new(move || {
+		// offline, the write waits in the outbox
+		if remote.is_connected() || remote.has_outbox() {
+			let remote = remote.clone();
+			let name = name.clone();
+
+			wasm_bindgen_futures::spawn_local(async move {
+				if let Err(err) = increment_counter(increment, &name, &remote).await {
+					web_sys::console::error_1(&err.into());
+				}
+			});
+		}
+	}) as Box<dyn FnMut()>)
+}
 
-	Ok(Closure::wrap(Box::new(move || {
-		if remote.is_connected() {
-			let document = document.dyn_ref::<web_sys::HtmlDocument>().unwrap();
-
-			let val: String = document
-				.get_element_by_id("value_display")
-				.unwrap_or_else(|| {
-					let res = document.create_element("span").unwrap();
-					res.set_inner_html("&nbsp;0&nbsp;");
-					res
-				})
-				.text_content()
-				.unwrap_or_else(|| String::from("0"));
-			let val = val.trim().parse::<isize>().unwrap_or_default() + increment as isize;
-
-			let save = remote
-				.put_document(COUNTER_PATH, &client::Document::from(val))
-				.unwrap();
-
-			let remote_for_callback = remote.clone();
-			let save_callback = Closure::wrap(Box::new(move |_: JsValue| {
-				update_counter_value(remote_for_callback.clone()).ok();
-			}) as Box<dyn FnMut(JsValue)>);
-			let err_callback = Closure::wrap(Box::new(move |err: JsValue| {
-				web_sys::console::error_1(&format!("{:?}", err).into())
-			}) as Box<dyn FnMut(JsValue)>);
-
-			save.then(&save_callback).catch(&err_callback);
-
-			save_callback.forget();
-			err_callback.forget();
+fn reset_trigger(name: &str, remote: std::rc::Rc<client::ClientRemote>) -> Closure<dyn FnMut()> {
+	let name = String::from(name);
+
+	Closure::wrap(Box::new(move || {
+		if remote.is_connected() || remote.has_outbox() {
+			let remote = remote.clone();
+			let name = name.clone();
+
+			wasm_bindgen_futures::spawn_local(async move {
+				if let Err(err) = reset_counter(&name, &remote).await {
+					web_sys::console::error_1(&err.into());
+				}
+			});
 		}
-	})))
+	}) as Box<dyn FnMut()>)
 }
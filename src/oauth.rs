@@ -0,0 +1,264 @@
+use wasm_bindgen::JsCast;
+
+use crate::{client::Error, utils};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OAuthFlow {
+	/// `response_type=token` : the token is given in the fragment of the
+	/// redirect URI.
+	#[default]
+	Implicit,
+	/// `response_type=code` with PKCE : a code is given in the query of the
+	/// redirect URI, then exchanged against tokens at the token endpoint.
+	AuthorizationCode,
+}
+impl OAuthFlow {
+	pub fn response_type(&self) -> &'static str {
+		match self {
+			Self::Implicit => "token",
+			Self::AuthorizationCode => "code",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectMode {
+	/// The whole page is redirected to the OAuth server, then back.
+	#[default]
+	Redirect,
+	/// The OAuth server is opened in a popup window, whose callback page
+	/// (`www/oauth-callback.html`) sends the response with `postMessage`.
+	Popup,
+}
+
+/// Which URI the OAuth server has to bring the user back to, in the redirect
+/// connect mode.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RedirectUri {
+	/// The root of the origin of the app, as `https://example.com/`.
+	Origin,
+	/// The current page, without its query nor fragment.
+	#[default]
+	CurrentPath,
+	Explicit(String),
+}
+impl RedirectUri {
+	pub fn resolve(&self, location: &web_sys::Location) -> Result<String, Error> {
+		match self {
+			Self::Origin => Ok(format!("{}/", location.origin()?)),
+			Self::CurrentPath => Ok(format!("{}{}", location.origin()?, location.pathname()?)),
+			Self::Explicit(redirect_uri) => Ok(redirect_uri.clone()),
+		}
+	}
+}
+
+// https://datatracker.ietf.org/doc/html/rfc6749#section-4.2.2
+// https://datatracker.ietf.org/doc/html/rfc6749#section-5.1
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct TokenResponse {
+	access_token: String,
+	#[serde(default = "default_token_type")]
+	token_type: String,
+	expires_in: Option<u64>,
+	refresh_token: Option<String>,
+	scope: Option<String>,
+	state: Option<String>,
+}
+fn default_token_type() -> String {
+	String::from("bearer")
+}
+impl TokenResponse {
+	pub fn get_access_token(&self) -> &str {
+		&self.access_token
+	}
+	pub fn get_token_type(&self) -> &str {
+		&self.token_type
+	}
+	pub fn get_expires_in(&self) -> Option<u64> {
+		self.expires_in
+	}
+	pub fn get_refresh_token(&self) -> Option<&str> {
+		self.refresh_token.as_deref()
+	}
+	pub fn get_scope(&self) -> Option<&str> {
+		self.scope.as_deref()
+	}
+	pub fn get_state(&self) -> Option<&str> {
+		self.state.as_deref()
+	}
+}
+
+// https://datatracker.ietf.org/doc/html/rfc6749#section-4.1.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeResponse {
+	code: String,
+	state: Option<String>,
+}
+impl CodeResponse {
+	pub fn get_code(&self) -> &str {
+		&self.code
+	}
+	pub fn get_state(&self) -> Option<&str> {
+		self.state.as_deref()
+	}
+}
+
+// https://datatracker.ietf.org/doc/html/rfc6749#section-4.2.2.1
+// https://datatracker.ietf.org/doc/html/rfc6749#section-5.2
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct AuthError {
+	error: String,
+	#[serde(rename = "error_description")]
+	description: Option<String>,
+	state: Option<String>,
+}
+impl AuthError {
+	pub fn get_error(&self) -> &str {
+		&self.error
+	}
+	pub fn get_description(&self) -> Option<&str> {
+		self.description.as_deref()
+	}
+	pub fn get_state(&self) -> Option<&str> {
+		self.state.as_deref()
+	}
+	pub fn is_access_denied(&self) -> bool {
+		self.error == "access_denied"
+	}
+}
+impl std::fmt::Display for AuthError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.description {
+			Some(description) => write!(f, "{} ({})", description, self.error),
+			None => f.write_str(&self.error),
+		}
+	}
+}
+
+/// Parses the `application/x-www-form-urlencoded` fragment the OAuth server
+/// appends to the redirect URI, with or without its leading `#`.
+///
+/// Returns `None` when the fragment is not an OAuth response at all.
+pub fn parse_fragment(fragment: &str) -> Option<Result<TokenResponse, AuthError>> {
+	let mut params = parse_form(fragment.strip_prefix('#').unwrap_or(fragment));
+
+	let state = params.remove("state");
+
+	if let Some(error) = params.remove("error") {
+		return Some(Err(AuthError {
+			error,
+			description: params.remove("error_description"),
+			state,
+		}));
+	}
+
+	let access_token = params
+		.remove("access_token")
+		.filter(|token| !token.is_empty())?;
+
+	Some(Ok(TokenResponse {
+		access_token,
+		token_type: params
+			.remove("token_type")
+			.unwrap_or_else(|| String::from("bearer")),
+		expires_in: params
+			.remove("expires_in")
+			.and_then(|expires_in| expires_in.parse().ok()),
+		refresh_token: None,
+		scope: params.remove("scope"),
+		state,
+	}))
+}
+
+/// Parses the query the OAuth server appends to the redirect URI in the
+/// authorization code flow, with or without its leading `?`.
+///
+/// Returns `None` when the query is not an OAuth response at all.
+pub fn parse_query(query: &str) -> Option<Result<CodeResponse, AuthError>> {
+	let mut params = parse_form(query.strip_prefix('?').unwrap_or(query));
+
+	let state = params.remove("state");
+
+	if let Some(error) = params.remove("error") {
+		return Some(Err(AuthError {
+			error,
+			description: params.remove("error_description"),
+			state,
+		}));
+	}
+
+	let code = params.remove("code").filter(|code| !code.is_empty())?;
+
+	Some(Ok(CodeResponse { code, state }))
+}
+
+fn parse_form(input: &str) -> std::collections::HashMap<String, String> {
+	let mut params = std::collections::HashMap::new();
+	for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+		let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+		params
+			.entry(form_decode(name))
+			.or_insert_with(|| form_decode(value));
+	}
+
+	params
+}
+
+fn form_decode(input: &str) -> String {
+	let input = input.replace('+', " ");
+
+	match pct_str::PctStr::new(&input) {
+		Ok(decoded) => decoded.decode(),
+		Err(_) => input,
+	}
+}
+
+// What has to be remembered during the redirection to the OAuth server.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct PendingAuthorization {
+	pub(crate) state: String,
+	pub(crate) redirect_uri: String,
+	pub(crate) code_verifier: Option<String>,
+}
+
+/// URL-safe base64 without padding, as needed by PKCE.
+pub fn base64url(bytes: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+	let mut result = String::with_capacity((bytes.len() * 4).div_ceil(3));
+	for chunk in bytes.chunks(3) {
+		let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, byte)| {
+			buffer | ((*byte as u32) << (16 - 8 * i))
+		});
+
+		for i in 0..=chunk.len() {
+			result.push(ALPHABET[((buffer >> (18 - 6 * i)) & 0x3F) as usize] as char);
+		}
+	}
+
+	result
+}
+
+fn crypto() -> Result<web_sys::Crypto, Error> {
+	Ok(utils::window()?.crypto()?)
+}
+
+pub(crate) fn random_token(length: usize) -> Result<String, Error> {
+	let mut bytes = vec![0u8; length];
+	crypto()?.get_random_values_with_u8_array(&mut bytes)?;
+
+	Ok(base64url(&bytes))
+}
+
+// https://datatracker.ietf.org/doc/html/rfc7636#section-4.2
+pub(crate) async fn code_challenge(code_verifier: &str) -> Result<String, Error> {
+	let mut bytes = code_verifier.as_bytes().to_vec();
+	let digest = crypto()?
+		.subtle()
+		.digest_with_str_and_u8_array("SHA-256", &mut bytes)?;
+	let digest = wasm_bindgen_futures::JsFuture::from(digest).await?;
+
+	Ok(base64url(
+		&js_sys::Uint8Array::new(digest.unchecked_ref()).to_vec(),
+	))
+}
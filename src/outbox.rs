@@ -0,0 +1,194 @@
+use crate::{
+	client::{Document, Error, Precondition},
+	conflict, idb,
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Operation {
+	Put(Document),
+	Delete,
+}
+
+/// A write which could not reach the server yet.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PendingWrite {
+	id: String,
+	path: String,
+	operation: Operation,
+	precondition: Precondition,
+	/// the version matching `precondition`, in order to resolve conflicts
+	#[serde(default)]
+	base: conflict::Base,
+	queued_at: f64,
+}
+impl PendingWrite {
+	/// A write queued at `queued_at`, which gets its id once pushed in the
+	/// outbox.
+	pub fn new(
+		path: impl Into<String>,
+		operation: Operation,
+		precondition: Precondition,
+		base: conflict::Base,
+		queued_at: f64,
+	) -> Self {
+		Self {
+			id: String::new(),
+			path: path.into(),
+			operation,
+			precondition,
+			base,
+			queued_at,
+		}
+	}
+	pub fn get_path(&self) -> &str {
+		&self.path
+	}
+	pub fn get_operation(&self) -> &Operation {
+		&self.operation
+	}
+	pub fn get_precondition(&self) -> &Precondition {
+		&self.precondition
+	}
+	pub fn get_base(&self) -> &conflict::Base {
+		&self.base
+	}
+	pub fn get_queued_at(&self) -> f64 {
+		self.queued_at
+	}
+	/// The precondition and the base of a write made on the version left in
+	/// the cache by this one : the server does not know it, so the write is
+	/// sent on the same version as this one, once this one is accepted.
+	pub fn following(&self) -> Option<(Precondition, conflict::Base)> {
+		match &self.operation {
+			Operation::Put(document) => Some((
+				self.precondition.clone(),
+				conflict::Base::Known(document.clone()),
+			)),
+			// the next write creates the document again
+			Operation::Delete => None,
+		}
+	}
+}
+
+/// Sends the queued writes of a document on the `ETag` returned by the
+/// previous one : they are all made on the version known before the first
+/// one.
+#[derive(Debug, Default)]
+pub struct ReplayChain {
+	// by path, as `(queued precondition, current ETag)`
+	etags: std::collections::HashMap<String, (Precondition, Option<String>)>,
+}
+impl ReplayChain {
+	/// The precondition to send `write` with.
+	pub fn precondition(&self, write: &PendingWrite) -> Precondition {
+		match (write.get_precondition(), self.etags.get(write.get_path())) {
+			(Precondition::Unconditional, _) => Precondition::Unconditional,
+			(queued, Some((previous, current))) if queued == previous => {
+				Precondition::from_etag(current.as_deref())
+			}
+			(queued, _) => queued.clone(),
+		}
+	}
+	/// `write` has been accepted, leaving the document with `etag`.
+	pub fn accepted(&mut self, write: &PendingWrite, etag: Option<String>) {
+		self.etags.insert(
+			String::from(write.get_path()),
+			(write.get_precondition().clone(), etag),
+		);
+	}
+	/// The next writes of `path` are made on a rejected version, so they have
+	/// to be resolved as conflicts as well.
+	pub fn rejected(&mut self, path: &str) {
+		self.etags.remove(path);
+	}
+}
+
+/// Keeps the writes made while offline in IndexedDB, in order to replay them
+/// once the server can be reached again.
+#[derive(Debug)]
+pub struct Outbox {
+	database: String,
+	// orders the writes queued during the same millisecond
+	sequence: std::cell::Cell<u32>,
+}
+impl Outbox {
+	const VERSION: u32 = 1;
+	const STORE: &'static str = "writes";
+
+	pub fn new(database: impl Into<String>) -> Self {
+		Self {
+			database: database.into(),
+			sequence: std::cell::Cell::new(0),
+		}
+	}
+	async fn open(&self) -> Result<web_sys::IdbDatabase, Error> {
+		idb::open(&self.database, Self::VERSION, &[Self::STORE]).await
+	}
+	/// Queues a write after the other ones of `namespace`.
+	pub async fn push(
+		&self,
+		namespace: &str,
+		path: impl Into<String>,
+		operation: Operation,
+		precondition: Precondition,
+		base: conflict::Base,
+	) -> Result<PendingWrite, Error> {
+		let queued_at = js_sys::Date::now();
+		let sequence = self.sequence.get();
+		self.sequence.set(sequence.wrapping_add(1));
+
+		let write = PendingWrite {
+			// keys are sorted as strings, hence the padding
+			id: format!(
+				"{}{:015}.{:010}",
+				key_prefix(namespace),
+				queued_at as u64,
+				sequence
+			),
+			..PendingWrite::new(path, operation, precondition, base, queued_at)
+		};
+		let value = serde_wasm_bindgen::to_value(&write)?;
+
+		let db = self.open().await?;
+		let result = idb::put(&db, Self::STORE, &write.id, &value).await;
+		db.close();
+
+		result.map(|_| write)
+	}
+	/// Writes of `namespace`, in the order they have been queued.
+	pub async fn pending(&self, namespace: &str) -> Result<Vec<PendingWrite>, Error> {
+		let db = self.open().await?;
+		let values = idb::get_all(&db, Self::STORE, &key_prefix(namespace)).await;
+		db.close();
+
+		values?
+			.into_iter()
+			.map(|value| Ok(serde_wasm_bindgen::from_value(value)?))
+			.collect()
+	}
+	pub async fn remove(&self, write: &PendingWrite) -> Result<(), Error> {
+		let db = self.open().await?;
+		let result = idb::delete(&db, Self::STORE, &write.id).await;
+		db.close();
+
+		result
+	}
+	/// Drops all the writes of `namespace`.
+	pub async fn clear(&self, namespace: &str) -> Result<(), Error> {
+		let db = self.open().await?;
+		let result = idb::delete_all(&db, Self::STORE, &key_prefix(namespace)).await;
+		db.close();
+
+		result
+	}
+}
+impl Default for Outbox {
+	fn default() -> Self {
+		Self::new("remotestorage_outbox")
+	}
+}
+
+// the separator prevents `a@b.c` from matching the writes of `a@b.co`
+fn key_prefix(namespace: &str) -> String {
+	format!("{}/", namespace)
+}
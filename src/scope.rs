@@ -0,0 +1,134 @@
+use crate::client::Error;
+
+const ROOT_MODULE: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Access {
+	Read,
+	ReadWrite,
+}
+impl std::fmt::Display for Access {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Read => f.write_str("r"),
+			Self::ReadWrite => f.write_str("rw"),
+		}
+	}
+}
+impl std::str::FromStr for Access {
+	type Err = Error;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		match input {
+			"r" => Ok(Self::Read),
+			"rw" => Ok(Self::ReadWrite),
+			_ => Err(Error::InvalidScope {
+				scope: String::from(input),
+				reason: "access must be `r` or `rw`",
+			}),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Scope {
+	module: String,
+	access: Access,
+}
+impl Scope {
+	pub fn new(module: impl Into<String>, access: Access) -> Self {
+		Self {
+			module: module.into(),
+			access,
+		}
+	}
+	pub fn root(access: Access) -> Self {
+		Self::new(ROOT_MODULE, access)
+	}
+	pub fn get_module(&self) -> &str {
+		&self.module
+	}
+	pub fn get_access(&self) -> Access {
+		self.access
+	}
+	pub fn is_root(&self) -> bool {
+		self.module == ROOT_MODULE
+	}
+	pub fn folder(&self) -> String {
+		if self.is_root() {
+			String::from("/")
+		} else {
+			format!("/{}/", self.module)
+		}
+	}
+	// a module scope also covers its public folder, as `/public/contacts/` for `contacts`
+	pub fn covers(&self, path: &str) -> bool {
+		self.is_root()
+			|| path.starts_with(&self.folder())
+			|| path
+				.strip_prefix("/public")
+				.map(|path| path.starts_with(&self.folder()))
+				.unwrap_or(false)
+	}
+	pub fn parse_list(input: &str) -> Result<Vec<Self>, Error> {
+		let result = input
+			.split_whitespace()
+			.map(str::parse)
+			.collect::<Result<Vec<Self>, Error>>()?;
+
+		if result.is_empty() {
+			return Err(Error::InvalidScope {
+				scope: String::from(input),
+				reason: "at least one scope is required",
+			});
+		}
+
+		Ok(result)
+	}
+	pub fn serialize_list(scopes: &[Self]) -> String {
+		scopes
+			.iter()
+			.map(Self::to_string)
+			.collect::<Vec<String>>()
+			.join(" ")
+	}
+	pub fn can_write(scopes: &[Self], path: &str) -> bool {
+		scopes
+			.iter()
+			.any(|scope| scope.access == Access::ReadWrite && scope.covers(path))
+	}
+}
+impl std::fmt::Display for Scope {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}", self.module, self.access)
+	}
+}
+impl std::str::FromStr for Scope {
+	type Err = Error;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (module, access) = input.split_once(':').ok_or_else(|| Error::InvalidScope {
+			scope: String::from(input),
+			reason: "scope must be written as `module:r` or `module:rw`",
+		})?;
+
+		let valid_module = module == ROOT_MODULE
+			|| (!module.is_empty()
+				&& module
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+		if !valid_module {
+			return Err(Error::InvalidScope {
+				scope: String::from(input),
+				reason: "module name must only contain letters, digits, `-` and `_`",
+			});
+		}
+
+		let access = access.parse().map_err(|_| Error::InvalidScope {
+			scope: String::from(input),
+			reason: "access must be `r` or `rw`",
+		})?;
+
+		Ok(Self::new(module, access))
+	}
+}
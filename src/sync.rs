@@ -0,0 +1,326 @@
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use crate::{
+	cache::{DocumentCache, Freshness},
+	client::{ClientRemote, Error, Folder},
+	utils,
+};
+
+/// What the `SyncEngine` is doing.
+pub enum SyncEvent<'a> {
+	Started,
+	/// The listing of `path` has been fetched ; an unchanged folder is not
+	/// walked any further.
+	FolderChecked {
+		path: &'a str,
+		changed: bool,
+	},
+	DocumentPulled {
+		path: &'a str,
+	},
+	/// The document has been removed from the server by an other client.
+	DocumentRemoved {
+		path: &'a str,
+	},
+	Finished(&'a SyncReport),
+	Failed(&'a Error),
+}
+
+// `setInterval` handle and callback
+type Timer = (i32, Closure<dyn FnMut()>);
+// walking the folder tree needs recursive futures
+type WalkFuture<'a> = std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + 'a>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+	pushed: usize,
+	pulled: Vec<String>,
+	removed: Vec<String>,
+	unchanged_folders: usize,
+	finished_at: f64,
+}
+impl SyncReport {
+	/// Count of writes sent from the outbox.
+	pub fn get_pushed(&self) -> usize {
+		self.pushed
+	}
+	pub fn get_pulled(&self) -> &[String] {
+		self.pulled.as_slice()
+	}
+	pub fn get_removed(&self) -> &[String] {
+		self.removed.as_slice()
+	}
+	pub fn get_unchanged_folders(&self) -> usize {
+		self.unchanged_folders
+	}
+	/// `Date.now()` at the end of the synchronization.
+	pub fn get_finished_at(&self) -> f64 {
+		self.finished_at
+	}
+}
+
+struct SyncInner {
+	remote: std::rc::Rc<ClientRemote>,
+	listeners: utils::Listeners<dyn Fn(&SyncEvent)>,
+	running: std::cell::Cell<bool>,
+	last_report: std::cell::RefCell<Option<SyncReport>>,
+	timer: std::cell::RefCell<Option<Timer>>,
+}
+impl Drop for SyncInner {
+	fn drop(&mut self) {
+		if let (Some(window), Some((handle, _))) = (web_sys::window(), self.timer.take()) {
+			window.clear_interval_with_handle(handle);
+		}
+	}
+}
+
+/// Keeps the document cache of a `ClientRemote` and its server in sync :
+/// the outbox is sent, then the folders of each scope are walked, only
+/// going down the subtrees whose `ETag` changed since the last time.
+///
+/// It requires a `DocumentCache`, see `ClientRemoteBuilder::cache`.
+#[derive(Clone)]
+pub struct SyncEngine {
+	inner: std::rc::Rc<SyncInner>,
+}
+impl SyncEngine {
+	pub fn new(remote: std::rc::Rc<ClientRemote>) -> Self {
+		Self {
+			inner: std::rc::Rc::new(SyncInner {
+				remote,
+				listeners: utils::Listeners::default(),
+				running: std::cell::Cell::new(false),
+				last_report: std::cell::RefCell::new(None),
+				timer: std::cell::RefCell::new(None),
+			}),
+		}
+	}
+	pub fn on_event(&self, listener: impl Fn(&SyncEvent) + 'static) {
+		self.inner.listeners.add(std::rc::Rc::new(listener));
+	}
+	pub fn get_last_report(&self) -> Option<SyncReport> {
+		self.inner.last_report.borrow().clone()
+	}
+	pub fn is_running(&self) -> bool {
+		self.inner.running.get()
+	}
+	/// Synchronizes now, then every `interval` while connected, until `stop`.
+	pub fn start(&self, interval: std::time::Duration) -> Result<(), Error> {
+		self.stop();
+
+		// a weak reference lets the engine be dropped while the timer runs
+		let weak_inner = std::rc::Rc::downgrade(&self.inner);
+		let tick = Closure::wrap(Box::new(move || {
+			if let Some(inner) = weak_inner.upgrade() {
+				let engine = SyncEngine { inner };
+				if engine.inner.remote.is_connected() {
+					wasm_bindgen_futures::spawn_local(async move {
+						engine.sync().await.ok();
+					});
+				}
+			}
+		}) as Box<dyn FnMut()>);
+
+		let handle = utils::window()?.set_interval_with_callback_and_timeout_and_arguments_0(
+			tick.as_ref().unchecked_ref(),
+			interval.as_millis().try_into().unwrap_or(i32::MAX),
+		)?;
+		self.inner.timer.replace(Some((handle, tick)));
+
+		if self.inner.remote.is_connected() {
+			let engine = self.clone();
+			wasm_bindgen_futures::spawn_local(async move {
+				engine.sync().await.ok();
+			});
+		}
+
+		Ok(())
+	}
+	pub fn stop(&self) {
+		if let Some((handle, tick)) = self.inner.timer.take() {
+			if let Some(window) = web_sys::window() {
+				window.clear_interval_with_handle(handle);
+			}
+			// `stop` may be called from a listener, while the tick runs
+			wasm_bindgen_futures::spawn_local(async move { drop(tick) });
+		}
+	}
+	/// Synchronizes once. Returns `None` if a synchronization is already
+	/// running.
+	pub async fn sync(&self) -> Result<Option<SyncReport>, Error> {
+		if self.inner.running.replace(true) {
+			return Ok(None);
+		}
+		self.notify(&SyncEvent::Started);
+
+		let mut report = SyncReport::default();
+		let result = self.run(&mut report).await;
+		self.inner.running.set(false);
+
+		match result {
+			Ok(()) => {
+				report.finished_at = js_sys::Date::now();
+				self.inner.last_report.replace(Some(report.clone()));
+				self.notify(&SyncEvent::Finished(&report));
+
+				Ok(Some(report))
+			}
+			Err(err) => {
+				self.notify(&SyncEvent::Failed(&err));
+
+				Err(err)
+			}
+		}
+	}
+	fn notify(&self, event: &SyncEvent) {
+		self.inner.listeners.for_each(|listener| listener(event));
+	}
+	async fn run(&self, report: &mut SyncReport) -> Result<(), Error> {
+		let remote = &self.inner.remote;
+		let cache = remote
+			.document_cache()
+			.ok_or(Error::MissingConfiguration("document cache"))?;
+
+		// local changes first, in order to pull them back with their `ETag`
+		let queued = remote.pending_writes().await?.len();
+		remote.replay_outbox().await?;
+		report.pushed = queued.saturating_sub(remote.pending_writes().await?.len());
+
+		for scope in remote.get_scopes() {
+			self.sync_folder(cache, scope.folder(), report).await?;
+		}
+
+		Ok(())
+	}
+	// The listing is only saved once the whole subtree is synchronized : an
+	// interrupted synchronization goes through it again next time.
+	fn sync_folder<'a>(
+		&'a self,
+		cache: &'a DocumentCache,
+		path: String,
+		report: &'a mut SyncReport,
+	) -> WalkFuture<'a> {
+		Box::pin(async move {
+			let remote = &self.inner.remote;
+			let key = remote.cache_key(&path);
+
+			let known = cache.get_folder(&key).await?.unwrap_or_default();
+			let listing = match remote.list_folder(path.as_str()).await {
+				Ok(listing) => listing,
+				// empty folders do not exist in remoteStorage
+				Err(Error::NotFound { .. }) => Folder::default(),
+				Err(err) => return Err(err),
+			};
+
+			let changed = known.get_etag().is_none() || known.get_etag() != listing.get_etag();
+			self.notify(&SyncEvent::FolderChecked {
+				path: &path,
+				changed,
+			});
+			if !changed {
+				report.unchanged_folders += 1;
+				return Ok(());
+			}
+
+			for document in listing.get_documents() {
+				let document_path = format!("{}{}", path, document.get_name());
+				let cached = cache.get(&remote.cache_key(&document_path)).await?;
+				let up_to_date = cached.is_some_and(|cached| {
+					cached.get_freshness() == Freshness::Pending
+						|| cached.get_document().get_etag() == Some(document.get_etag())
+				});
+
+				if !up_to_date {
+					match remote.read_document(document_path.as_str()).await {
+						Ok(cached) if cached.get_freshness() == Freshness::Fresh => {
+							self.notify(&SyncEvent::DocumentPulled {
+								path: &document_path,
+							});
+							report.pulled.push(document_path);
+						}
+						Ok(_) | Err(Error::NotFound { .. }) => {}
+						Err(err) => return Err(err),
+					}
+				}
+			}
+			for document in known.get_documents() {
+				if !listing
+					.get_documents()
+					.iter()
+					.any(|listed| listed.get_name() == document.get_name())
+				{
+					let document_path = format!("{}{}", path, document.get_name());
+					self.forget_document(cache, document_path, report).await?;
+				}
+			}
+
+			for folder in listing.get_folders() {
+				let unchanged = known.get_folders().iter().any(|known| {
+					known.get_name() == folder.get_name() && known.get_etag() == folder.get_etag()
+				});
+				if unchanged {
+					report.unchanged_folders += 1;
+				} else {
+					self.sync_folder(cache, format!("{}{}/", path, folder.get_name()), report)
+						.await?;
+				}
+			}
+			for folder in known.get_folders() {
+				if !listing
+					.get_folders()
+					.iter()
+					.any(|listed| listed.get_name() == folder.get_name())
+				{
+					self.forget_folder(cache, format!("{}{}/", path, folder.get_name()), report)
+						.await?;
+				}
+			}
+
+			cache.put_folder(&key, &listing).await
+		})
+	}
+	async fn forget_document(
+		&self,
+		cache: &DocumentCache,
+		path: String,
+		report: &mut SyncReport,
+	) -> Result<(), Error> {
+		let key = self.inner.remote.cache_key(&path);
+
+		// a queued write will recreate it
+		if let Some(cached) = cache.get(&key).await? {
+			if cached.get_freshness() == Freshness::Pending {
+				return Ok(());
+			}
+		}
+
+		self.inner.remote.forget_cached_document(&path).await;
+		self.notify(&SyncEvent::DocumentRemoved { path: &path });
+		report.removed.push(path);
+
+		Ok(())
+	}
+	fn forget_folder<'a>(
+		&'a self,
+		cache: &'a DocumentCache,
+		path: String,
+		report: &'a mut SyncReport,
+	) -> WalkFuture<'a> {
+		Box::pin(async move {
+			let key = self.inner.remote.cache_key(&path);
+
+			if let Some(known) = cache.get_folder(&key).await? {
+				for document in known.get_documents() {
+					self.forget_document(cache, format!("{}{}", path, document.get_name()), report)
+						.await?;
+				}
+				for folder in known.get_folders() {
+					self.forget_folder(cache, format!("{}{}/", path, folder.get_name()), report)
+						.await?;
+				}
+			}
+
+			cache.remove_folder(&key).await
+		})
+	}
+}
@@ -0,0 +1,298 @@
+use wasm_bindgen::JsCast;
+
+use crate::{
+	client::Error,
+	idb, oauth,
+	utils::{html_document, window},
+	webfinger,
+};
+
+pub type StoreFuture<'a, T> =
+	std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, Error>> + 'a>>;
+
+// Futures are needed because some browser storages (as IndexedDB) are only
+// reachable asynchronously.
+pub trait TokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>>;
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()>;
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// What is actually kept by the `TokenStore`s : the token and its expiry date.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StoredToken {
+	access_token: String,
+	// milliseconds since UNIX epoch, as returned by `Date.now()`
+	expires_at: Option<f64>,
+	#[serde(default)]
+	refresh_token: Option<String>,
+	/// the remoteStorage link of the user, in order to connect offline
+	#[serde(default)]
+	storage_link: Option<webfinger::Link>,
+}
+impl StoredToken {
+	pub fn new(access_token: impl Into<String>, expires_in: Option<std::time::Duration>) -> Self {
+		Self {
+			access_token: access_token.into(),
+			expires_at: expires_in
+				.map(|expires_in| js_sys::Date::now() + expires_in.as_millis() as f64),
+			refresh_token: None,
+			storage_link: None,
+		}
+	}
+	pub fn from_response(response: &oauth::TokenResponse) -> Self {
+		Self::new(
+			response.get_access_token(),
+			response
+				.get_expires_in()
+				.map(std::time::Duration::from_secs),
+		)
+		.with_refresh_token(response.get_refresh_token().map(String::from))
+	}
+	pub fn with_refresh_token(mut self, refresh_token: Option<String>) -> Self {
+		self.refresh_token = refresh_token;
+		self
+	}
+	pub fn with_storage_link(mut self, storage_link: Option<webfinger::Link>) -> Self {
+		self.storage_link = storage_link;
+		self
+	}
+	pub fn get_access_token(&self) -> &str {
+		&self.access_token
+	}
+	pub fn get_expires_at(&self) -> Option<f64> {
+		self.expires_at
+	}
+	pub fn get_refresh_token(&self) -> Option<&str> {
+		self.refresh_token.as_deref()
+	}
+	pub fn get_storage_link(&self) -> Option<&webfinger::Link> {
+		self.storage_link.as_ref()
+	}
+	pub fn is_expired(&self) -> bool {
+		match self.expires_at {
+			Some(expires_at) => js_sys::Date::now() >= expires_at,
+			None => false,
+		}
+	}
+	pub fn serialize(&self) -> String {
+		serde_json::to_string(self).unwrap_or_default()
+	}
+	// tokens saved by previous versions are stored as is, without expiry date
+	pub fn parse(value: &str) -> Self {
+		serde_json::from_str(value).unwrap_or_else(|_| Self::new(value, None))
+	}
+}
+
+/// Keeps the token in `window.localStorage`, shared by all tabs of the origin
+/// and kept across browser restarts.
+#[derive(Debug, Clone, Default)]
+pub struct LocalStorageTokenStore;
+impl TokenStore for LocalStorageTokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+		Box::pin(async move { web_storage_load(window()?.local_storage()?, key) })
+	}
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move { web_storage_save(window()?.local_storage()?, key, token) })
+	}
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move { web_storage_remove(window()?.local_storage()?, key) })
+	}
+}
+
+/// Keeps the token in `window.sessionStorage`, which is dropped with the tab.
+#[derive(Debug, Clone, Default)]
+pub struct SessionStorageTokenStore;
+impl TokenStore for SessionStorageTokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+		Box::pin(async move { web_storage_load(window()?.session_storage()?, key) })
+	}
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move { web_storage_save(window()?.session_storage()?, key, token) })
+	}
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move { web_storage_remove(window()?.session_storage()?, key) })
+	}
+}
+
+fn web_storage_load(storage: Option<web_sys::Storage>, key: &str) -> Result<Option<String>, Error> {
+	Ok(storage
+		.ok_or(Error::Unsupported("Web Storage"))?
+		.get_item(key)?)
+}
+
+fn web_storage_save(
+	storage: Option<web_sys::Storage>,
+	key: &str,
+	token: &str,
+) -> Result<(), Error> {
+	Ok(storage
+		.ok_or(Error::Unsupported("Web Storage"))?
+		.set_item(key, token)?)
+}
+
+fn web_storage_remove(storage: Option<web_sys::Storage>, key: &str) -> Result<(), Error> {
+	Ok(storage
+		.ok_or(Error::Unsupported("Web Storage"))?
+		.remove_item(key)?)
+}
+
+/// Keeps the token in an IndexedDB object store.
+#[derive(Debug, Clone)]
+pub struct IndexedDbTokenStore {
+	database: String,
+}
+impl IndexedDbTokenStore {
+	const VERSION: u32 = 1;
+	const STORE: &'static str = "tokens";
+
+	pub fn new(database: impl Into<String>) -> Self {
+		Self {
+			database: database.into(),
+		}
+	}
+	async fn open(&self) -> Result<web_sys::IdbDatabase, Error> {
+		idb::open(&self.database, Self::VERSION, &[Self::STORE]).await
+	}
+}
+impl Default for IndexedDbTokenStore {
+	fn default() -> Self {
+		Self::new("remotestorage_tokens")
+	}
+}
+impl TokenStore for IndexedDbTokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+		Box::pin(async move {
+			let db = self.open().await?;
+			let token = idb::get(&db, Self::STORE, key).await;
+			db.close();
+
+			Ok(token?.and_then(|token| token.as_string()))
+		})
+	}
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			let db = self.open().await?;
+			let result = idb::put(&db, Self::STORE, key, &token.into()).await;
+			db.close();
+
+			result
+		})
+	}
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			let db = self.open().await?;
+			let result = idb::delete(&db, Self::STORE, key).await;
+			db.close();
+
+			result
+		})
+	}
+}
+
+/// Keeps the token only as long as this store lives : the user has to
+/// connect again after each page load.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+	tokens: std::cell::RefCell<std::collections::HashMap<String, String>>,
+}
+impl TokenStore for MemoryTokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+		Box::pin(async move { Ok(self.tokens.borrow().get(key).cloned()) })
+	}
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			self.tokens
+				.borrow_mut()
+				.insert(String::from(key), String::from(token));
+
+			Ok(())
+		})
+	}
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			self.tokens.borrow_mut().remove(key);
+
+			Ok(())
+		})
+	}
+}
+
+/// Keeps the token in a cookie.
+///
+/// Beware that this cookie is sent along with every request to the origin of
+/// the application.
+#[derive(Debug, Clone)]
+pub struct CookieTokenStore {
+	path: String,
+	max_age: std::time::Duration,
+}
+impl CookieTokenStore {
+	pub fn new(path: impl Into<String>, max_age: std::time::Duration) -> Self {
+		Self {
+			path: path.into(),
+			max_age,
+		}
+	}
+	fn attributes(&self, max_age: u64) -> String {
+		format!(
+			"Path={}; Max-Age={}; Secure; SameSite=Strict",
+			self.path, max_age
+		)
+	}
+}
+/// Removes the cookie in which the previous versions kept the token, without
+/// any attribute.
+pub fn remove_legacy_cookie(key: &str) -> Result<(), Error> {
+	html_document()?.set_cookie(&format!("{}=; Max-Age=0", key))?;
+
+	Ok(())
+}
+
+impl Default for CookieTokenStore {
+	fn default() -> Self {
+		Self::new("/", std::time::Duration::from_secs(30 * 24 * 60 * 60))
+	}
+}
+impl TokenStore for CookieTokenStore {
+	fn load<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<String>> {
+		Box::pin(async move {
+			let all_cookies = html_document()?.cookie()?;
+
+			let mut result = None;
+			for cookie in all_cookies.split(';') {
+				let mut iter = cookie.split('=');
+				let name = iter.next().map(str::trim);
+				let value = iter
+					.next()
+					.and_then(|res| pct_str::PctString::new(res.trim()).ok())
+					.map(|res| res.decode());
+
+				if name == Some(key) {
+					result = value;
+				}
+			}
+
+			Ok(result)
+		})
+	}
+	fn save<'a>(&'a self, key: &'a str, token: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			html_document()?.set_cookie(&format!(
+				"{}={}; {}",
+				key,
+				pct_str::PctString::encode(token.chars(), pct_str::URIReserved),
+				self.attributes(self.max_age.as_secs())
+			))?;
+
+			Ok(())
+		})
+	}
+	fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+		Box::pin(async move {
+			html_document()?.set_cookie(&format!("{}=; {}", key, self.attributes(0)))?;
+
+			Ok(())
+		})
+	}
+}
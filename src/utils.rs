@@ -1,3 +1,7 @@
+use wasm_bindgen::JsCast;
+
+use crate::client::Error;
+
 pub fn set_panic_hook() {
 	// Lorsque la fonctionnalité `console_error_panic_hook` est activée, nous
 	// pouvons faire appel à la fonction `set_panic_hook` au moins une fois à
@@ -9,3 +13,55 @@ pub fn set_panic_hook() {
 	#[cfg(feature = "console_error_panic_hook")]
 	console_error_panic_hook::set_once();
 }
+
+pub(crate) fn window() -> Result<web_sys::Window, Error> {
+	web_sys::window().ok_or_else(|| Error::Js(String::from("window not found")))
+}
+
+pub(crate) fn document() -> Result<web_sys::Document, Error> {
+	window()?
+		.document()
+		.ok_or_else(|| Error::Js(String::from("document not found")))
+}
+
+pub(crate) fn html_document() -> Result<web_sys::HtmlDocument, Error> {
+	document()?
+		.dyn_into::<web_sys::HtmlDocument>()
+		.map_err(|_| Error::Js(String::from("document can not be casted into HtmlDocument")))
+}
+
+/// Listeners of an event, called in the order they have been added.
+pub(crate) struct Listeners<F: ?Sized> {
+	listeners: std::cell::RefCell<Vec<(usize, std::rc::Rc<F>)>>,
+	next_id: std::cell::Cell<usize>,
+}
+impl<F: ?Sized> Listeners<F> {
+	/// Returns the id to remove the listener with.
+	pub(crate) fn add(&self, listener: std::rc::Rc<F>) -> usize {
+		let id = self.next_id.get();
+		self.next_id.set(id + 1);
+		self.listeners.borrow_mut().push((id, listener));
+
+		id
+	}
+	pub(crate) fn remove(&self, id: usize) {
+		self.listeners
+			.borrow_mut()
+			.retain(|(listener_id, _)| *listener_id != id);
+	}
+	pub(crate) fn for_each(&self, call: impl Fn(&F)) {
+		// listeners are cloned in order to let them register other listeners
+		let listeners = self.listeners.borrow().clone();
+		for (_, listener) in listeners {
+			call(&listener);
+		}
+	}
+}
+impl<F: ?Sized> Default for Listeners<F> {
+	fn default() -> Self {
+		Self {
+			listeners: std::cell::RefCell::new(vec![]),
+			next_id: std::cell::Cell::new(0),
+		}
+	}
+}
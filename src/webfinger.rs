@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::client::Error;
+
+// `rel` of the remoteStorage link, by order of preference :
+// the current drafts, then the older `draft-dejong-remotestorage-00` and `-01`,
+// then the legacy one used by early implementations.
+pub const STORAGE_RELS: [&str; 3] = [
+	"http://tools.ietf.org/id/draft-dejong-remotestorage",
+	"remotestorage",
+	"remoteStorage",
+];
+
+pub const VERSION_KEY: &str = "http://remotestorage.io/spec/version";
+pub const OAUTH_KEY: &str = "http://tools.ietf.org/html/rfc6749#section-4.2";
+pub const QUERY_TOKEN_KEY: &str = "http://tools.ietf.org/html/rfc6750#section-2.3";
+pub const RANGE_KEY: &str = "http://tools.ietf.org/html/rfc7233";
+pub const WEB_AUTHORING_KEY: &str = "http://remotestorage.io/spec/web-authoring";
+pub const REVOCATION_KEY: &str = "http://tools.ietf.org/html/rfc7009";
+// token endpoint of the authorization code flow
+pub const TOKEN_ENDPOINT_KEY: &str = "http://tools.ietf.org/html/rfc6749#section-3.2";
+// used instead of `OAUTH_KEY` by servers implementing the older drafts
+const LEGACY_OAUTH_KEY: &str = "auth-endpoint";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct UserAddress {
+	user: String,
+	host: String,
+}
+impl UserAddress {
+	pub fn get_user(&self) -> &str {
+		&self.user
+	}
+	pub fn get_host(&self) -> &str {
+		&self.host
+	}
+	pub fn resource(&self) -> String {
+		format!("acct:{}", self)
+	}
+	// `webfinger_root` overrides the default `https://<host>`, as for local
+	// servers like `http://localhost:7541`.
+	pub fn webfinger_url(&self, webfinger_root: Option<&str>) -> String {
+		let default_root = format!("https://{}", self.host);
+		let webfinger_root = webfinger_root.unwrap_or(&default_root);
+
+		format!(
+			"{}/.well-known/webfinger?resource={}",
+			webfinger_root.strip_suffix('/').unwrap_or(webfinger_root),
+			pct_str::PctString::encode(self.resource().chars(), pct_str::URIReserved)
+		)
+	}
+}
+impl std::fmt::Display for UserAddress {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}@{}", self.user, self.host)
+	}
+}
+impl std::str::FromStr for UserAddress {
+	type Err = Error;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let input = input.trim();
+		let input = input.strip_prefix("acct:").unwrap_or(input);
+
+		let invalid = |reason| Error::InvalidUserAddress {
+			address: String::from(input),
+			reason,
+		};
+
+		let (user, host) = input
+			.rsplit_once('@')
+			.ok_or_else(|| invalid("address must be written as `user@host`"))?;
+
+		if user.is_empty() {
+			return Err(invalid("user part is empty"));
+		}
+		if user
+			.chars()
+			.any(|c| c.is_whitespace() || c.is_control() || c == '/')
+		{
+			return Err(invalid("user part contains forbidden characters"));
+		}
+
+		let authority = host
+			.parse::<http::uri::Authority>()
+			.map_err(|_| invalid("host part is not a valid host name"))?;
+		if authority.host().is_empty() || host.contains('@') {
+			return Err(invalid("host part is not a valid host name"));
+		}
+
+		Ok(Self {
+			user: String::from(user),
+			host: host.to_lowercase(),
+		})
+	}
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WebfingerResponse {
+	subject: Option<String>,
+	#[serde(default)]
+	aliases: Vec<String>,
+	#[serde(default)]
+	properties: HashMap<String, Option<String>>,
+	#[serde(default)]
+	links: Vec<Link>,
+}
+impl WebfingerResponse {
+	pub fn get_subject(&self) -> Option<&str> {
+		self.subject.as_deref()
+	}
+	pub fn get_aliases(&self) -> &[String] {
+		self.aliases.as_slice()
+	}
+	pub fn get_properties(&self) -> &HashMap<String, Option<String>> {
+		&self.properties
+	}
+	pub fn get_links(&self) -> &[Link] {
+		self.links.as_slice()
+	}
+	pub fn storage_link(&self) -> Option<&Link> {
+		STORAGE_RELS
+			.iter()
+			.find_map(|rel| self.links.iter().find(|link| link.rel == *rel))
+	}
+	/// The remoteStorage link of `user_address`, which must have an `href`.
+	pub fn try_storage_link(&self, user_address: &UserAddress) -> Result<&Link, Error> {
+		match self.storage_link() {
+			Some(link) if link.get_href().is_some() => Ok(link),
+			Some(link) => Err(Error::Webfinger(format!(
+				"the `{}` link of the webfinger response has no `href`",
+				link.get_rel()
+			))),
+			None => Err(Error::Webfinger(format!(
+				"no remoteStorage link (with `rel` among {}) in the webfinger response of `{}`",
+				STORAGE_RELS
+					.iter()
+					.map(|rel| format!("`{}`", rel))
+					.collect::<Vec<String>>()
+					.join(", "),
+				self.get_subject()
+					.map(String::from)
+					.unwrap_or_else(|| user_address.resource())
+			))),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Link {
+	rel: String,
+	#[serde(rename = "type")]
+	mime_type: Option<String>,
+	href: Option<String>,
+	#[serde(default)]
+	properties: HashMap<String, Option<String>>,
+}
+impl Link {
+	pub fn get_rel(&self) -> &str {
+		&self.rel
+	}
+	pub fn get_type(&self) -> Option<&str> {
+		self.mime_type.as_deref()
+	}
+	pub fn get_href(&self) -> Option<&str> {
+		self.href.as_deref()
+	}
+	pub fn get_property(&self, name: &str) -> Option<&str> {
+		self.properties.get(name).and_then(Option::as_deref)
+	}
+	pub fn get_properties(&self) -> &HashMap<String, Option<String>> {
+		&self.properties
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StorageCapabilities {
+	version: Option<String>,
+	auth_endpoint: Option<String>,
+	query_token: bool,
+	range_requests: bool,
+	web_authoring: Option<String>,
+	revocation_endpoint: Option<String>,
+	token_endpoint: Option<String>,
+}
+impl StorageCapabilities {
+	pub fn from_link(link: &Link) -> Self {
+		Self {
+			version: link.get_property(VERSION_KEY).map(String::from),
+			auth_endpoint: link
+				.get_property(OAUTH_KEY)
+				.or_else(|| link.get_property(LEGACY_OAUTH_KEY))
+				.map(String::from),
+			query_token: link
+				.get_property(QUERY_TOKEN_KEY)
+				.map(|value| value.eq_ignore_ascii_case("true"))
+				.unwrap_or(false),
+			range_requests: link
+				.get_property(RANGE_KEY)
+				.map(|value| {
+					value.eq_ignore_ascii_case("GET") || value.eq_ignore_ascii_case("true")
+				})
+				.unwrap_or(false),
+			web_authoring: link
+				.get_property(WEB_AUTHORING_KEY)
+				.filter(|value| !value.is_empty())
+				.map(String::from),
+			revocation_endpoint: link
+				.get_property(REVOCATION_KEY)
+				.filter(|value| !value.is_empty())
+				.map(String::from),
+			token_endpoint: link
+				.get_property(TOKEN_ENDPOINT_KEY)
+				.filter(|value| !value.is_empty())
+				.map(String::from),
+		}
+	}
+	pub fn get_version(&self) -> Option<&str> {
+		self.version.as_deref()
+	}
+	pub fn get_auth_endpoint(&self) -> Option<&str> {
+		self.auth_endpoint.as_deref()
+	}
+	pub fn supports_query_token(&self) -> bool {
+		self.query_token
+	}
+	pub fn supports_range_requests(&self) -> bool {
+		self.range_requests
+	}
+	pub fn get_web_authoring_domain(&self) -> Option<&str> {
+		self.web_authoring.as_deref()
+	}
+	pub fn get_revocation_endpoint(&self) -> Option<&str> {
+		self.revocation_endpoint.as_deref()
+	}
+	pub fn get_token_endpoint(&self) -> Option<&str> {
+		self.token_endpoint.as_deref()
+	}
+}
@@ -0,0 +1,363 @@
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use crate::{
+	client::Error,
+	i18n::{Catalog, MessageKey},
+	utils, webfinger,
+};
+
+/// What the `ConnectWidget` displays. Each state is reflected by the
+/// `rs-widget--<state>` class of the widget root, see `www/connect-widget.css`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidgetState {
+	Disconnected,
+	EnteringAddress,
+	Authorizing {
+		auth_url: String,
+		server: String,
+		popup: bool,
+	},
+	Connected {
+		address: String,
+	},
+	Syncing,
+	Error(String),
+}
+impl WidgetState {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Disconnected => "disconnected",
+			Self::EnteringAddress => "entering-address",
+			Self::Authorizing { .. } => "authorizing",
+			Self::Connected { .. } => "connected",
+			Self::Syncing => "syncing",
+			Self::Error(_) => "error",
+		}
+	}
+}
+
+/// What the user asked for, through the widget.
+pub enum WidgetAction<'a> {
+	AddressSubmitted(webfinger::UserAddress),
+	/// The authorization link has been clicked ; call `prevent_default` on
+	/// the event to avoid the navigation.
+	Authorize(&'a web_sys::Event),
+	Abort,
+	Disconnect,
+}
+
+type EventHandler = Closure<dyn FnMut(web_sys::Event)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerId(usize);
+
+struct WidgetInner {
+	root: web_sys::Element,
+	state: std::cell::RefCell<WidgetState>,
+	address: std::cell::RefCell<Option<webfinger::UserAddress>>,
+	catalog: std::cell::RefCell<Catalog>,
+	listeners: utils::Listeners<dyn Fn(&WidgetAction)>,
+	// handlers of the currently rendered elements
+	closures: std::cell::RefCell<Vec<EventHandler>>,
+}
+
+/// The connection UI, without any inline style : its look only relies on
+/// the `rs-widget*` CSS classes.
+#[derive(Clone)]
+pub struct ConnectWidget {
+	inner: std::rc::Rc<WidgetInner>,
+}
+impl ConnectWidget {
+	pub fn mount(container: &web_sys::Element) -> Result<Self, Error> {
+		let root = utils::document()?.create_element("div")?;
+		container.append_child(&root)?;
+
+		let widget = Self {
+			inner: std::rc::Rc::new(WidgetInner {
+				root,
+				state: std::cell::RefCell::new(WidgetState::Disconnected),
+				address: std::cell::RefCell::new(None),
+				catalog: std::cell::RefCell::new(Catalog::from_navigator()),
+				listeners: utils::Listeners::default(),
+				closures: std::cell::RefCell::new(vec![]),
+			}),
+		};
+		widget.render()?;
+
+		Ok(widget)
+	}
+	pub fn unmount(&self) {
+		self.inner.root.remove();
+		self.release_closures();
+	}
+	pub fn get_element(&self) -> &web_sys::Element {
+		&self.inner.root
+	}
+	pub fn get_state(&self) -> WidgetState {
+		self.inner.state.borrow().clone()
+	}
+	pub fn set_state(&self, state: WidgetState) -> Result<(), Error> {
+		self.inner.state.replace(state);
+		self.render()
+	}
+	/// Prefills the address field.
+	pub fn set_address(&self, address: Option<webfinger::UserAddress>) -> Result<(), Error> {
+		self.inner.address.replace(address);
+		self.render()
+	}
+	/// Replaces the texts, which follow `navigator.language` by default.
+	pub fn set_catalog(&self, catalog: Catalog) -> Result<(), Error> {
+		self.inner.catalog.replace(catalog);
+		self.render()
+	}
+	pub fn get_catalog(&self) -> Catalog {
+		self.inner.catalog.borrow().clone()
+	}
+	pub fn on_action(&self, listener: impl Fn(&WidgetAction) + 'static) -> ListenerId {
+		ListenerId(self.inner.listeners.add(std::rc::Rc::new(listener)))
+	}
+	pub fn remove_action_listener(&self, id: ListenerId) {
+		self.inner.listeners.remove(id.0);
+	}
+	pub fn downgrade(&self) -> WeakConnectWidget {
+		WeakConnectWidget {
+			inner: std::rc::Rc::downgrade(&self.inner),
+		}
+	}
+	fn notify(&self, action: &WidgetAction) {
+		self.inner.listeners.for_each(|listener| listener(action));
+	}
+	fn render(&self) -> Result<(), Error> {
+		let document = utils::document()?;
+		let root = &self.inner.root;
+		let state = self.get_state();
+		let catalog = self.get_catalog();
+
+		root.set_inner_html("");
+		self.release_closures();
+		root.set_class_name(&format!("rs-widget rs-widget--{}", state.as_str()));
+
+		let logo = element(&document, "span", "rs-widget__logo")?;
+		root.append_child(&logo)?;
+
+		let message = element(&document, "p", "rs-widget__message")?;
+		let actions = element(&document, "p", "rs-widget__actions")?;
+
+		match &state {
+			WidgetState::Disconnected => {
+				message.set_text_content(Some(catalog.get(MessageKey::ConnectPrompt)));
+				let connect = self.button(
+					&document,
+					"connect",
+					catalog.get(MessageKey::ConnectButton),
+					|widget, _| {
+						widget.set_state(WidgetState::EnteringAddress).ok();
+					},
+				)?;
+				actions.append_child(&connect)?;
+			}
+			WidgetState::EnteringAddress => {
+				let form = element(&document, "form", "rs-widget__form")?;
+
+				let label = element(&document, "label", "rs-widget__label")?;
+				label.set_text_content(Some(catalog.get(MessageKey::AddressLabel)));
+				form.append_child(&label)?;
+
+				let input = element(&document, "input", "rs-widget__address")?;
+				input.set_attribute("type", "text")?;
+				input.set_attribute("name", "address")?;
+				input.set_attribute("placeholder", catalog.get(MessageKey::AddressPlaceholder))?;
+				input.set_attribute("autocomplete", "username")?;
+				if let Some(address) = &*self.inner.address.borrow() {
+					input.set_attribute("value", &address.to_string())?;
+				}
+				label.append_child(&input)?;
+
+				let submit = element(&document, "button", "rs-widget__button")?;
+				submit.class_list().add_1("rs-widget__button--submit")?;
+				submit.set_attribute("type", "submit")?;
+				submit.set_text_content(Some(catalog.get(MessageKey::SubmitButton)));
+				form.append_child(&submit)?;
+
+				let input = input.dyn_into::<web_sys::HtmlInputElement>().map_err(|_| {
+					Error::Js(String::from("can not cast input as HtmlInputElement"))
+				})?;
+				self.listen(&form, "submit", move |widget, event| {
+					event.prevent_default();
+
+					match input.value().parse::<webfinger::UserAddress>() {
+						Ok(address) => {
+							widget.inner.address.replace(Some(address.clone()));
+							widget.notify(&WidgetAction::AddressSubmitted(address));
+						}
+						Err(err) => {
+							let text = widget.get_catalog().error(&err);
+							widget.set_state(WidgetState::Error(text)).ok();
+						}
+					}
+				})?;
+
+				message.append_child(&form)?;
+
+				let abort = self.button(
+					&document,
+					"abort",
+					catalog.get(MessageKey::AbortButton),
+					|widget, _| {
+						widget.notify(&WidgetAction::Abort);
+					},
+				)?;
+				actions.append_child(&abort)?;
+			}
+			WidgetState::Authorizing {
+				auth_url,
+				server,
+				popup,
+			} => {
+				message.append_child(&document.create_text_node(catalog.get(if *popup {
+					MessageKey::PopupBefore
+				} else {
+					MessageKey::RedirectBefore
+				})))?;
+				let server_link = element(&document, "a", "rs-widget__server")?;
+				server_link.set_attribute("href", auth_url)?;
+				server_link.set_text_content(Some(server));
+				// opens the popup too, as the next button
+				self.listen(&server_link, "click", |widget, event| {
+					widget.notify(&WidgetAction::Authorize(event));
+				})?;
+				message.append_child(&server_link)?;
+				message.append_child(&document.create_text_node(catalog.get(if *popup {
+					MessageKey::PopupAfter
+				} else {
+					MessageKey::RedirectAfter
+				})))?;
+
+				let abort = self.button(
+					&document,
+					"abort",
+					catalog.get(MessageKey::AbortButton),
+					|widget, _| {
+						widget.notify(&WidgetAction::Abort);
+					},
+				)?;
+				actions.append_child(&abort)?;
+
+				let next = element(&document, "a", "rs-widget__button")?;
+				next.class_list().add_1("rs-widget__button--authorize")?;
+				next.set_attribute("href", auth_url)?;
+				next.set_text_content(Some(catalog.get(MessageKey::NextButton)));
+				self.listen(&next, "click", |widget, event| {
+					widget.notify(&WidgetAction::Authorize(event));
+				})?;
+				actions.append_child(&next)?;
+			}
+			WidgetState::Connected { address } => {
+				message
+					.set_text_content(Some(&catalog.format(MessageKey::ConnectedAs, &[address])));
+				let disconnect = self.button(
+					&document,
+					"disconnect",
+					catalog.get(MessageKey::DisconnectButton),
+					|widget, _| {
+						widget.notify(&WidgetAction::Disconnect);
+					},
+				)?;
+				actions.append_child(&disconnect)?;
+			}
+			WidgetState::Syncing => {
+				message.set_text_content(Some(catalog.get(MessageKey::Syncing)));
+			}
+			WidgetState::Error(err) => {
+				message.set_text_content(Some(err));
+				let abort = self.button(
+					&document,
+					"abort",
+					catalog.get(MessageKey::AbortButton),
+					|widget, _| {
+						widget.notify(&WidgetAction::Abort);
+					},
+				)?;
+				actions.append_child(&abort)?;
+				let retry = self.button(
+					&document,
+					"retry",
+					catalog.get(MessageKey::RetryButton),
+					|widget, _| {
+						widget.set_state(WidgetState::EnteringAddress).ok();
+					},
+				)?;
+				actions.append_child(&retry)?;
+			}
+		}
+
+		root.append_child(&message)?;
+		root.append_child(&actions)?;
+
+		Ok(())
+	}
+	// Rendering usually happens from one of the handlers, which can not be
+	// dropped while it runs : they are dropped once the current event is done.
+	fn release_closures(&self) {
+		let closures = self.inner.closures.take();
+		if !closures.is_empty() {
+			wasm_bindgen_futures::spawn_local(async move { drop(closures) });
+		}
+	}
+	fn button(
+		&self,
+		document: &web_sys::Document,
+		name: &str,
+		text: &str,
+		handler: impl Fn(&ConnectWidget, &web_sys::Event) + 'static,
+	) -> Result<web_sys::Element, Error> {
+		let button = element(document, "button", "rs-widget__button")?;
+		button
+			.class_list()
+			.add_1(&format!("rs-widget__button--{}", name))?;
+		button.set_attribute("type", "button")?;
+		button.set_text_content(Some(text));
+		self.listen(&button, "click", handler)?;
+
+		Ok(button)
+	}
+	fn listen(
+		&self,
+		target: &web_sys::Element,
+		event_type: &str,
+		handler: impl Fn(&ConnectWidget, &web_sys::Event) + 'static,
+	) -> Result<(), Error> {
+		// a weak reference avoids the cycle between the widget and its handlers
+		let weak_widget = self.downgrade();
+		let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+			if let Some(widget) = weak_widget.upgrade() {
+				handler(&widget, &event);
+			}
+		}) as Box<dyn FnMut(web_sys::Event)>);
+		target.add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())?;
+		self.inner.closures.borrow_mut().push(closure);
+
+		Ok(())
+	}
+}
+
+#[derive(Clone)]
+pub struct WeakConnectWidget {
+	inner: std::rc::Weak<WidgetInner>,
+}
+impl WeakConnectWidget {
+	pub fn upgrade(&self) -> Option<ConnectWidget> {
+		self.inner.upgrade().map(|inner| ConnectWidget { inner })
+	}
+}
+
+fn element(
+	document: &web_sys::Document,
+	tag: &str,
+	class: &str,
+) -> Result<web_sys::Element, Error> {
+	let element = document.create_element(tag)?;
+	element.set_class_name(class);
+
+	Ok(element)
+}
@@ -11,3 +11,483 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn succes() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn scopes_round_trip() {
+    use test_bindgen_fetch::scope::{Access, Scope};
+
+    let scopes = Scope::parse_list("contacts:r experimental_counter:rw *:rw").unwrap();
+
+    assert_eq!(scopes[0], Scope::new("contacts", Access::Read));
+    assert_eq!(scopes[1], Scope::new("experimental_counter", Access::ReadWrite));
+    assert!(scopes[2].is_root());
+    assert_eq!(
+        Scope::serialize_list(&scopes),
+        "contacts:r experimental_counter:rw *:rw"
+    );
+}
+
+#[wasm_bindgen_test]
+fn scopes_reject_writes_outside_rw_modules() {
+    use test_bindgen_fetch::scope::Scope;
+
+    let scopes = Scope::parse_list("contacts:r experimental_counter:rw").unwrap();
+
+    assert!(Scope::can_write(&scopes, "/experimental_counter/counter"));
+    assert!(Scope::can_write(&scopes, "/public/experimental_counter/counter"));
+    assert!(!Scope::can_write(&scopes, "/contacts/alice"));
+    assert!(!Scope::can_write(&scopes, "/experimental_counter_2/counter"));
+    assert!(Scope::parse_list("contacts").is_err());
+    assert!(Scope::parse_list("contacts:x").is_err());
+    assert!(Scope::parse_list("").is_err());
+}
+
+#[wasm_bindgen_test]
+fn user_address_derives_webfinger_url() {
+    use test_bindgen_fetch::webfinger::UserAddress;
+
+    let address: UserAddress = "toto@localhost:7541".parse().unwrap();
+
+    assert_eq!(address.get_user(), "toto");
+    assert_eq!(address.get_host(), "localhost:7541");
+    assert_eq!(
+        address.webfinger_url(None),
+        "https://localhost:7541/.well-known/webfinger?resource=acct%3Atoto%40localhost%3A7541"
+    );
+    assert_eq!(
+        address.webfinger_url(Some("http://localhost:7541/")),
+        "http://localhost:7541/.well-known/webfinger?resource=acct%3Atoto%40localhost%3A7541"
+    );
+    assert!("toto".parse::<UserAddress>().is_err());
+    assert!("@localhost".parse::<UserAddress>().is_err());
+    assert!("toto@".parse::<UserAddress>().is_err());
+}
+
+#[wasm_bindgen_test]
+fn oauth_fragment_parsing() {
+    use test_bindgen_fetch::oauth::parse_fragment;
+
+    let token = parse_fragment("#token_type=bearer&state=abc&access_token=a%2Fb+c")
+        .unwrap()
+        .unwrap();
+    assert_eq!(token.get_access_token(), "a/b c");
+    assert_eq!(token.get_state(), Some("abc"));
+    assert_eq!(token.get_expires_in(), None);
+
+    let err = parse_fragment("#error=access_denied&error_description=User+denied&state=abc")
+        .unwrap()
+        .unwrap_err();
+    assert!(err.is_access_denied());
+    assert_eq!(err.get_description(), Some("User denied"));
+
+    assert!(parse_fragment("#section-2").is_none());
+    assert!(parse_fragment("").is_none());
+}
+
+#[wasm_bindgen_test]
+fn oauth_code_query_parsing() {
+    use test_bindgen_fetch::oauth::{base64url, parse_query};
+
+    let code = parse_query("?code=SplxlOBeZQQYbYS6WxSbIA&state=xyz")
+        .unwrap()
+        .unwrap();
+    assert_eq!(code.get_code(), "SplxlOBeZQQYbYS6WxSbIA");
+    assert_eq!(code.get_state(), Some("xyz"));
+    assert!(parse_query("?error=access_denied").unwrap().is_err());
+    assert!(parse_query("?page=2").is_none());
+
+    assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+    assert_eq!(base64url(b"remoteStorage"), "cmVtb3RlU3RvcmFnZQ");
+}
+
+#[wasm_bindgen_test]
+fn catalog_locales_and_overrides() {
+    use test_bindgen_fetch::{
+        client::Error,
+        i18n::{Catalog, Locale, MessageKey},
+        webfinger::UserAddress,
+    };
+
+    assert_eq!(Locale::from_tag("fr-CA"), Locale::Fr);
+    assert_eq!(Locale::from_tag("FR"), Locale::Fr);
+    assert_eq!(Locale::from_tag("en-US"), Locale::En);
+    assert_eq!(Locale::from_tag("de"), Locale::En);
+
+    let catalog = Catalog::new(Locale::Fr).with_override(MessageKey::NextButton, "Continuer");
+    assert_eq!(catalog.get(MessageKey::AbortButton), "Annuler");
+    assert_eq!(catalog.get(MessageKey::NextButton), "Continuer");
+    assert_eq!(
+        catalog.format(MessageKey::ConnectedAs, &["toto@localhost"]),
+        "Connecté en tant que toto@localhost"
+    );
+
+    let invalid = "toto".parse::<UserAddress>().unwrap_err();
+    assert_eq!(catalog.error(&invalid), catalog.get(MessageKey::InvalidAddress));
+    assert_eq!(
+        catalog.error(&Error::Network(String::from("offline"))),
+        "Le serveur est injoignable, veuillez vérifier votre connexion."
+    );
+    let invalid_client_id = Error::InvalidClientId {
+        client_id: String::from("localhost"),
+        reason: "the client id must be an absolute URL",
+    };
+    assert_eq!(invalid_client_id.kind(), "InvalidClientId");
+    assert_eq!(
+        catalog.error(&invalid_client_id),
+        catalog.get(MessageKey::InvalidClientId)
+    );
+    assert_eq!(
+        catalog.error(&Error::NotConnected),
+        format!("Une erreur est survenue : {}", Error::NotConnected)
+    );
+}
+
+#[wasm_bindgen_test]
+fn change_dispatching() {
+    use test_bindgen_fetch::{
+        change::{Change, ChangeOrigin},
+        client::Document,
+    };
+
+    let path = "/experimental_counter/counter";
+    let written = Change::new(path, None, Some(Document::from(1)), ChangeOrigin::Local);
+    assert!(!written.is_noop());
+    assert!(written.concerns("/experimental_counter/"));
+    assert!(written.concerns(""));
+    assert!(!written.concerns("/experimental_counter/counter/"));
+    assert!(!written.concerns("/contacts/"));
+    assert!(written.is_broadcast());
+
+    // the cache already had this version
+    let same = Change::new(
+        path,
+        Some(Document::from(1)),
+        Some(Document::from(1)),
+        ChangeOrigin::Remote,
+    );
+    assert!(same.is_noop());
+    assert!(Change::new(path, None, None, ChangeOrigin::Remote).is_noop());
+
+    // received from an other tab, which already shared it
+    let shared = Change::new(path, None, Some(Document::from(1)), ChangeOrigin::Window);
+    assert!(!shared.is_broadcast());
+    assert!(Change::new(path, None, None, ChangeOrigin::Conflict).is_broadcast());
+}
+
+#[wasm_bindgen_test]
+fn webfinger_storage_link_by_rel() {
+    use test_bindgen_fetch::webfinger::{StorageCapabilities, UserAddress, WebfingerResponse};
+
+    let address: UserAddress = "toto@localhost:7541".parse().unwrap();
+    let jrd = r#"{
+        "subject": "acct:toto@localhost:7541",
+        "links": [
+            { "rel": "http://webfinger.net/rel/profile-page", "href": "https://localhost:7541/toto" },
+            { "rel": "remoteStorage", "href": "https://localhost:7541/legacy/toto" },
+            {
+                "rel": "http://tools.ietf.org/id/draft-dejong-remotestorage",
+                "href": "https://localhost:7541/storage/toto",
+                "properties": {
+                    "http://remotestorage.io/spec/version": "draft-dejong-remotestorage-22",
+                    "http://tools.ietf.org/html/rfc6749#section-4.2": "https://localhost:7541/oauth/toto"
+                }
+            }
+        ]
+    }"#;
+    let response: WebfingerResponse = serde_json::from_str(jrd).unwrap();
+
+    let link = response.try_storage_link(&address).unwrap();
+    assert_eq!(link.get_href(), Some("https://localhost:7541/storage/toto"));
+    assert_eq!(
+        StorageCapabilities::from_link(link).get_auth_endpoint(),
+        Some("https://localhost:7541/oauth/toto")
+    );
+
+    let legacy: WebfingerResponse = serde_json::from_str(
+        r#"{ "links": [
+            { "rel": "http://webfinger.net/rel/avatar", "href": "https://localhost:7541/toto.png" },
+            { "rel": "remoteStorage", "href": "https://localhost:7541/legacy/toto" },
+            { "rel": "remotestorage", "href": "https://localhost:7541/draft-01/toto" }
+        ] }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        legacy.try_storage_link(&address).unwrap().get_href(),
+        Some("https://localhost:7541/draft-01/toto")
+    );
+
+    let without_href: WebfingerResponse =
+        serde_json::from_str(r#"{ "links": [ { "rel": "remotestorage" } ] }"#).unwrap();
+    assert!(without_href.storage_link().is_some());
+    assert!(without_href.try_storage_link(&address).is_err());
+
+    let without_storage: WebfingerResponse = serde_json::from_str(
+        r#"{ "links": [ { "rel": "http://webfinger.net/rel/profile-page", "href": "https://localhost:7541/toto" } ] }"#,
+    )
+    .unwrap();
+    assert!(without_storage.try_storage_link(&address).is_err());
+}
+
+#[wasm_bindgen_test]
+fn storage_capabilities_from_link() {
+    use test_bindgen_fetch::webfinger::{StorageCapabilities, WebfingerResponse};
+
+    let capabilities = |properties: &str| {
+        let jrd = format!(
+            r#"{{ "links": [ {{ "rel": "remotestorage", "href": "https://localhost:7541/toto", "properties": {} }} ] }}"#,
+            properties
+        );
+        let response: WebfingerResponse = serde_json::from_str(&jrd).unwrap();
+        StorageCapabilities::from_link(response.storage_link().unwrap())
+    };
+
+    let range = "http://tools.ietf.org/html/rfc7233";
+    assert!(capabilities(&format!(r#"{{ "{}": "GET" }}"#, range)).supports_range_requests());
+    assert!(capabilities(&format!(r#"{{ "{}": "true" }}"#, range)).supports_range_requests());
+    assert!(!capabilities(&format!(r#"{{ "{}": null }}"#, range)).supports_range_requests());
+    assert!(!capabilities("{}").supports_range_requests());
+
+    let query_token =
+        capabilities(r#"{ "http://tools.ietf.org/html/rfc6750#section-2.3": "true" }"#);
+    assert!(query_token.supports_query_token());
+    assert!(!capabilities("{}").supports_query_token());
+
+    let web_authoring = "http://remotestorage.io/spec/web-authoring";
+    assert_eq!(
+        capabilities(&format!(r#"{{ "{}": "" }}"#, web_authoring)).get_web_authoring_domain(),
+        None
+    );
+    assert_eq!(
+        capabilities(&format!(r#"{{ "{}": "toto.localhost" }}"#, web_authoring))
+            .get_web_authoring_domain(),
+        Some("toto.localhost")
+    );
+
+    assert_eq!(
+        capabilities(r#"{ "auth-endpoint": "https://localhost:7541/legacy-oauth" }"#)
+            .get_auth_endpoint(),
+        Some("https://localhost:7541/legacy-oauth")
+    );
+    assert_eq!(
+        capabilities(
+            r#"{
+                "auth-endpoint": "https://localhost:7541/legacy-oauth",
+                "http://tools.ietf.org/html/rfc6749#section-4.2": "https://localhost:7541/oauth"
+            }"#
+        )
+        .get_auth_endpoint(),
+        Some("https://localhost:7541/oauth")
+    );
+}
+
+#[wasm_bindgen_test]
+fn folder_listings() {
+    use test_bindgen_fetch::client::Folder;
+
+    let current = Folder::parse(
+        Some(String::from("\"folder\"")),
+        r#"{
+            "@context": "http://remotestorage.io/spec/folder-description",
+            "items": {
+                "notes/": { "ETag": "\"notes\"" },
+                "b.json": { "ETag": "\"b\"", "Content-Type": "application/json", "Content-Length": 12 },
+                "a.txt": { "ETag": "\"a\"" }
+            }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(current.get_etag(), Some("\"folder\""));
+    let names: Vec<&str> = current.get_documents().iter().map(|doc| doc.get_name()).collect();
+    assert_eq!(names, ["a.txt", "b.json"]);
+    assert_eq!(current.get_documents()[0].get_content_type(), None);
+    assert_eq!(current.get_documents()[1].get_content_type(), Some("application/json"));
+    assert_eq!(current.get_documents()[1].get_content_length(), Some(12));
+    assert_eq!(current.get_folders().len(), 1);
+    assert_eq!(current.get_folders()[0].get_name(), "notes");
+    assert_eq!(current.get_folders()[0].get_etag(), "\"notes\"");
+
+    let legacy = Folder::parse(None, r#"{ "a.txt": 1400000000000, "notes/": "12345" }"#).unwrap();
+    assert_eq!(legacy.get_etag(), None);
+    assert_eq!(legacy.get_documents()[0].get_name(), "a.txt");
+    assert_eq!(legacy.get_documents()[0].get_etag(), "1400000000000");
+    assert_eq!(legacy.get_folders()[0].get_etag(), "12345");
+
+    assert!(Folder::parse(
+        None,
+        r#"{ "@context": "http://remotestorage.io/spec/folder-description" }"#
+    )
+    .is_err());
+
+    let empty = Folder::parse(None, "{}").unwrap();
+    assert!(empty.get_documents().is_empty());
+    assert!(empty.get_folders().is_empty());
+}
+
+#[wasm_bindgen_test]
+fn conflict_resolvers() {
+    use test_bindgen_fetch::{
+        client::Document,
+        conflict::{
+            Base, Conflict, ConflictResolver, LastModifiedWins, LocalWins, Merge, RemoteWins,
+            Resolution,
+        },
+        merge_counters,
+    };
+
+    let counter =
+        |value: isize, etag: &str| Document::from(value).with_etag(Some(String::from(etag)));
+    let modified_at = |last_modified: f64| -> Document {
+        serde_json::from_value(serde_json::json!({
+            "etag": "\"2\"",
+            "content": [],
+            "content_type": "text/plain",
+            "last_modified": last_modified,
+        }))
+        .unwrap()
+    };
+    let value =
+        |document: Option<Document>| document.map(|document| document.get_content().to_vec());
+
+    let conflict = Conflict::new(
+        "/experimental_counter/counter",
+        Base::Known(counter(1, "\"1\"")),
+        Some(Document::from(3)),
+        Some(counter(5, "\"2\"")),
+        1000.0,
+    );
+    assert_eq!(RemoteWins.resolve(&conflict), Resolution::Remote);
+    assert_eq!(LocalWins.resolve(&conflict), Resolution::Local);
+    // the server version does not tell when it was modified
+    assert_eq!(LastModifiedWins.resolve(&conflict), Resolution::Local);
+    assert_eq!(
+        Merge::new(|_: &Conflict| None).resolve(&conflict),
+        Resolution::Merged(None)
+    );
+    // +2 locally, +4 on the server
+    assert_eq!(value(merge_counters(&conflict)), value(Some(Document::from(7))));
+
+    let newer = Conflict::new("/a", Base::Unknown, None, Some(modified_at(2000.0)), 1000.0);
+    assert_eq!(LastModifiedWins.resolve(&newer), Resolution::Remote);
+    let older = Conflict::new("/a", Base::Unknown, None, Some(modified_at(500.0)), 1000.0);
+    assert_eq!(LastModifiedWins.resolve(&older), Resolution::Local);
+
+    // created on both sides
+    let created = Conflict::new(
+        "/experimental_counter/counter",
+        Base::Missing,
+        Some(Document::from(1)),
+        Some(counter(2, "\"1\"")),
+        1000.0,
+    );
+    assert!(created.is_base_known());
+    assert_eq!(value(merge_counters(&created)), value(Some(Document::from(3))));
+
+    // the local increment can not be known
+    let unknown = Conflict::new(
+        "/experimental_counter/counter",
+        Base::Unknown,
+        Some(Document::from(3)),
+        Some(counter(5, "\"2\"")),
+        1000.0,
+    );
+    assert!(!unknown.is_base_known());
+    assert_eq!(merge_counters(&unknown), Some(counter(5, "\"2\"")));
+
+    // a reset wins
+    let reset = Conflict::new(
+        "/experimental_counter/counter",
+        Base::Known(counter(1, "\"1\"")),
+        None,
+        Some(counter(5, "\"2\"")),
+        1000.0,
+    );
+    assert_eq!(merge_counters(&reset), None);
+}
+
+#[wasm_bindgen_test]
+fn outbox_chains_writes_of_a_document() {
+    use test_bindgen_fetch::{
+        client::{Document, Precondition},
+        conflict::{Base, Conflict},
+        merge_counters,
+        outbox::{Operation, PendingWrite, ReplayChain},
+    };
+
+    let path = "/experimental_counter/counter";
+    let etag = |etag: &str| Some(String::from(etag));
+    let value =
+        |document: Option<Document>| document.map(|document| document.get_content().to_vec());
+
+    // two increments from 5 while offline, the second one made on the first
+    let first = PendingWrite::new(
+        path,
+        Operation::Put(Document::from(6)),
+        Precondition::IfMatch(String::from("\"1\"")),
+        Base::Known(Document::from(5).with_etag(etag("\"1\""))),
+        1000.0,
+    );
+    let (precondition, base) = first.following().unwrap();
+    assert_eq!(precondition, Precondition::IfMatch(String::from("\"1\"")));
+    let second = PendingWrite::new(
+        path,
+        Operation::Put(Document::from(7)),
+        precondition,
+        base,
+        2000.0,
+    );
+
+    let mut chain = ReplayChain::default();
+    assert_eq!(
+        chain.precondition(&first),
+        Precondition::IfMatch(String::from("\"1\""))
+    );
+    chain.accepted(&first, etag("\"2\""));
+    assert_eq!(
+        chain.precondition(&second),
+        Precondition::IfMatch(String::from("\"2\""))
+    );
+
+    // an other device added 4 before the replay : both writes are resolved
+    let mut chain = ReplayChain::default();
+    chain.rejected(path);
+    let conflict = Conflict::new(
+        path,
+        first.get_base().clone(),
+        Some(Document::from(6)),
+        Some(Document::from(9).with_etag(etag("\"2\""))),
+        first.get_queued_at(),
+    );
+    assert_eq!(value(merge_counters(&conflict)), value(Some(Document::from(10))));
+    assert_eq!(
+        chain.precondition(&second),
+        Precondition::IfMatch(String::from("\"1\""))
+    );
+    let conflict = Conflict::new(
+        path,
+        second.get_base().clone(),
+        Some(Document::from(7)),
+        Some(Document::from(10).with_etag(etag("\"3\""))),
+        second.get_queued_at(),
+    );
+    assert_eq!(value(merge_counters(&conflict)), value(Some(Document::from(11))));
+
+    // a document created again after a deletion
+    let deleted = PendingWrite::new(
+        path,
+        Operation::Delete,
+        Precondition::IfMatch(String::from("\"1\"")),
+        Base::Unknown,
+        1000.0,
+    );
+    assert!(deleted.following().is_none());
+    let created = PendingWrite::new(
+        path,
+        Operation::Put(Document::from(1)),
+        Precondition::CreateOnly,
+        Base::Missing,
+        2000.0,
+    );
+    let mut chain = ReplayChain::default();
+    chain.accepted(&deleted, None);
+    assert_eq!(chain.precondition(&created), Precondition::CreateOnly);
+}
@@ -1,4 +1,4 @@
 import * as wasm from "test_bindgen_fetch";
 
-wasm.run("http://localhost:7541", "toto", "experimental_counter:rw")
+wasm.run("toto@localhost:7541", "http://localhost:7541", "contacts:r experimental_counter:rw")
 	.catch(console.error)
@@ -9,6 +9,6 @@ module.exports = {
   },
   mode: "development",
   plugins: [
-    new CopyWebpackPlugin(['index.html'])
+    new CopyWebpackPlugin(['index.html', 'oauth-callback.html', 'connect-widget.css', 'counters.css', 'remoteStorage.svg'])
   ],
 };